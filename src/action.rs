@@ -0,0 +1,190 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::guard::{display_value, quote_if_needed, variable_name};
+use crate::{StateId, StateType, StateTypeLookup, StateTypeValue};
+
+/// Something the runtime does when a state is entered or exited, or a [`crate::Transition`] is
+/// taken.
+///
+/// Variables are referenced by id like in guards, so renaming one only changes how the action is
+/// displayed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+    /// `Speed = 3` sets a variable. Setting a trigger fires it for the next step.
+    Set {
+        variable: StateId,
+        value: StateTypeValue,
+    },
+    /// `emit Landed` reports a named event to whatever runs the machine, and fires the trigger
+    /// with the same name, if there is one, for the next step.
+    Emit(String),
+    /// `start Cooldown` sets a float variable to 0 and counts the seconds passing in it.
+    StartTimer(StateId),
+    /// `stop Cooldown` stops counting, keeping the variable at the time it got to.
+    StopTimer(StateId),
+}
+
+impl Action {
+    /// Reads a list of actions separated by `;`, as [`Action::display_list`] writes them.
+    pub fn parse_list(source: &str, state_types: &impl StateTypeLookup) -> Result<Vec<Self>> {
+        split_list(source)?
+            .into_iter()
+            .map(|item| Self::parse(item, state_types))
+            .collect()
+    }
+
+    pub fn parse(source: &str, state_types: &impl StateTypeLookup) -> Result<Self> {
+        let source = source.trim();
+        // A variable can be called `start` too, but then it's followed by `=`
+        let keyword = source
+            .split_once(char::is_whitespace)
+            .filter(|(keyword, rest)| {
+                ["emit", "start", "stop"].contains(keyword) && !rest.trim_start().starts_with('=')
+            });
+        if let Some((keyword, name)) = keyword {
+            let name = unquote(name.trim());
+            if keyword == "emit" {
+                return Ok(Action::Emit(name));
+            }
+            let data = state_types
+                .state_type_named(&name)
+                .ok_or_else(|| format!("No state variable named {name}"))?;
+            check_timer(&data.name, &data.state_type)?;
+            return Ok(if keyword == "start" {
+                Action::StartTimer(data.id.clone())
+            } else {
+                Action::StopTimer(data.id.clone())
+            });
+        }
+        let (name, value) = source
+            .split_once('=')
+            .ok_or_else(|| format!("Expected emit, start, stop or = in action {source:?}"))?;
+        let name = unquote(name.trim());
+        let data = state_types
+            .state_type_named(&name)
+            .ok_or_else(|| format!("No state variable named {name}"))?;
+        let value = data
+            .state_type
+            .parse_value(&unquote(value.trim()))
+            .ok_or_else(|| format!("{} can't be set to {}", data.name, value.trim()))?;
+        Ok(Action::Set {
+            variable: data.id.clone(),
+            value,
+        })
+    }
+
+    /// Writes the actions back out in the syntax [`Action::parse_list`] reads, using the current
+    /// names.
+    pub fn display_list(actions: &[Action], state_types: &impl StateTypeLookup) -> String {
+        actions
+            .iter()
+            .map(|action| action.display(state_types))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn display(&self, state_types: &impl StateTypeLookup) -> String {
+        match self {
+            Action::Set { variable, value } => format!(
+                "{} = {}",
+                variable_name(variable, state_types),
+                display_value(value)
+            ),
+            Action::Emit(event) => format!("emit {}", quote_if_needed(event)),
+            Action::StartTimer(variable) => {
+                format!("start {}", variable_name(variable, state_types))
+            }
+            Action::StopTimer(variable) => format!("stop {}", variable_name(variable, state_types)),
+        }
+    }
+
+    /// The variable this action changes, if any.
+    pub fn variable(&self) -> Option<&StateId> {
+        match self {
+            Action::Set { variable, .. }
+            | Action::StartTimer(variable)
+            | Action::StopTimer(variable) => Some(variable),
+            Action::Emit(_) => None,
+        }
+    }
+
+    /// Everything that stopped making sense since the action was written, e.g. because the
+    /// variable it sets was removed or changed type.
+    pub fn problems(&self, state_types: &impl StateTypeLookup) -> Vec<String> {
+        let Some(variable) = self.variable() else {
+            return vec![];
+        };
+        let Some(data) = state_types.state_type(variable) else {
+            return vec!["Sets a state variable that no longer exists".to_string()];
+        };
+        let problem = match self {
+            Action::Set { value, .. } if !data.state_type.accepts(value) => Some(format!(
+                "{} can't be set to {}",
+                data.name,
+                display_value(value)
+            )),
+            Action::StartTimer(_) | Action::StopTimer(_) => {
+                check_timer(&data.name, &data.state_type).err()
+            }
+            _ => None,
+        };
+        problem.into_iter().collect()
+    }
+}
+
+/// Timers count seconds, which only a float can hold.
+fn check_timer(name: &str, state_type: &StateType) -> Result<(), String> {
+    match state_type {
+        StateType::Float { .. } => Ok(()),
+        state_type => Err(format!(
+            "{name} is a {state_type} and can't be used as a timer"
+        )),
+    }
+}
+
+/// Splits on the `;`s that aren't inside quotes, leaving out empty items.
+fn split_list(source: &str) -> Result<Vec<&str>> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut chars = source.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            // An escaped quote doesn't end the name, see [`quote_if_needed`]
+            '\\' if quoted => {
+                chars.next();
+            }
+            ';' if !quoted => {
+                items.push(&source[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if quoted {
+        return Err("Unterminated quote in actions".into());
+    }
+    items.push(&source[start..]);
+    Ok(items
+        .into_iter()
+        .filter(|item| !item.trim().is_empty())
+        .collect())
+}
+
+/// Undoes [`quote_if_needed`], leaving unquoted text as it is.
+fn unquote(text: &str) -> String {
+    let Some(quoted) = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+    else {
+        return text.to_string();
+    };
+    let mut unquoted = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        unquoted.extend(if c == '\\' { chars.next() } else { Some(c) });
+    }
+    unquoted
+}
@@ -0,0 +1,248 @@
+//! Keyboard and gamepad input driving state types while the machine is played.
+
+use std::fmt::{Display, Formatter};
+
+use bevy::color::palettes::css;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::editor::SidePanelEntry;
+use crate::history::{Edit, Perform};
+use crate::simulation::{Simulation, step_simulation};
+use crate::text_input::TextInputActive;
+use crate::{StateId, StateType, StateTypeValue, StateTypes};
+
+/// How far an axis has to be pushed to count as held, or to be picked when binding.
+const AXIS_THRESHOLD: f32 = 0.5;
+
+pub struct BindingsPlugin;
+
+impl Plugin for BindingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                add_binding_buttons,
+                update_binding_buttons,
+                apply_bindings
+                    .before(step_simulation)
+                    .run_if(resource_exists::<Simulation>),
+            ),
+        )
+        // After every shortcut has seen the button listening, so the input that's bound doesn't
+        // also do whatever it does in the editor
+        .add_systems(PostUpdate, capture_binding);
+    }
+}
+
+/// A physical input bound to a state type.
+///
+/// Bools are held while the input is, triggers fire when it's pressed, and ints and floats follow
+/// an axis, or are 1 while a button is held.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputBinding {
+    Key(KeyCode),
+    GamepadButton(GamepadButton),
+    GamepadAxis(GamepadAxis),
+}
+
+impl Display for InputBinding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InputBinding::Key(key) => write!(f, "Key {key:?}"),
+            InputBinding::GamepadButton(button) => write!(f, "Pad {button:?}"),
+            InputBinding::GamepadAxis(axis) => write!(f, "Pad {axis:?}"),
+        }
+    }
+}
+
+impl InputBinding {
+    /// Buttons are 0 or 1, axes go from -1 to 1. The strongest of all connected gamepads wins.
+    pub fn value(&self, keys: &ButtonInput<KeyCode>, gamepads: &Query<&Gamepad>) -> f32 {
+        let pressed = |pressed: bool| if pressed { 1.0 } else { 0.0 };
+        match self {
+            InputBinding::Key(key) => pressed(keys.pressed(*key)),
+            InputBinding::GamepadButton(button) => {
+                pressed(gamepads.iter().any(|gamepad| gamepad.pressed(*button)))
+            }
+            InputBinding::GamepadAxis(axis) => gamepads
+                .iter()
+                .filter_map(|gamepad| gamepad.get(*axis))
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+/// Run condition for shortcuts, so keys bound to the running machine don't also edit it.
+pub fn no_bound_key_pressed(
+    state_types: Res<StateTypes>,
+    keys: Res<ButtonInput<KeyCode>>,
+    simulation: Option<Res<Simulation>>,
+) -> bool {
+    simulation.is_none()
+        || !state_types.values().any(
+            |data| matches!(data.binding, Some(InputBinding::Key(key)) if keys.just_pressed(key)),
+        )
+}
+
+/// Enums have nothing sensible to map a button or axis to.
+fn is_bindable(state_type: &StateType) -> bool {
+    !matches!(state_type, StateType::Enum(_))
+}
+
+/// Binds the next key, gamepad button or axis to its state type once clicked. Backspace or Delete
+/// unbinds it instead.
+///
+/// Listening counts as an active text input, so shortcuts leave the keys alone in the meantime.
+#[derive(Component)]
+#[require(Button, TextInputActive)]
+struct BindingButton(StateId);
+
+/// A side panel entry that has its [`BindingButton`].
+#[derive(Component)]
+struct HasBindingButton;
+
+fn add_binding_buttons(
+    entries: Query<(Entity, &SidePanelEntry), Without<HasBindingButton>>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) {
+    for (entry, SidePanelEntry(id)) in entries.iter() {
+        commands.entity(entry).insert(HasBindingButton);
+        if !state_types
+            .get(id)
+            .is_some_and(|data| is_bindable(&data.state_type))
+        {
+            continue;
+        }
+        // Labelled by `update_binding_buttons`
+        commands.spawn((
+            Node {
+                justify_content: JustifyContent::Center,
+                margin: UiRect::top(Val::Px(5.0)),
+                border: UiRect::all(Val::Px(3.0)),
+                ..default()
+            },
+            BackgroundColor(css::GRAY.into()),
+            BorderColor(css::BLACK.into()),
+            BorderRadius::all(Val::Px(3.0)),
+            BindingButton(id.clone()),
+            ChildOf(entry),
+            children![(Text::default(), TextFont::from_font_size(12.0))],
+        ));
+    }
+}
+
+fn update_binding_buttons(
+    mut buttons: Query<(
+        &BindingButton,
+        &TextInputActive,
+        &Children,
+        &mut BorderColor,
+    )>,
+    mut texts: Query<&mut Text>,
+    state_types: Res<StateTypes>,
+) {
+    for (BindingButton(id), active, children, mut border) in buttons.iter_mut() {
+        let listening = *active == TextInputActive::Active;
+        let label = match state_types.get(id).and_then(|data| data.binding) {
+            _ if listening => "Press an input...".to_string(),
+            Some(binding) => binding.to_string(),
+            None => "Bind input".to_string(),
+        };
+        border.set_if_neq(BorderColor(if listening {
+            css::WHITE.into()
+        } else {
+            css::BLACK.into()
+        }));
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.set_if_neq(Text(label.clone()));
+            }
+        }
+    }
+}
+
+fn capture_binding(
+    mut buttons: Query<(&BindingButton, &mut TextInputActive)>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) {
+    for (BindingButton(id), mut active) in buttons.iter_mut() {
+        if *active != TextInputActive::Active {
+            continue;
+        }
+        let new = if keys.any_just_pressed([KeyCode::Backspace, KeyCode::Delete]) {
+            None
+        } else if let Some(key) = keys.get_just_pressed().next() {
+            Some(InputBinding::Key(*key))
+        } else if let Some(button) = gamepads
+            .iter()
+            .find_map(|gamepad| gamepad.get_just_pressed().next())
+        {
+            Some(InputBinding::GamepadButton(*button))
+        } else if let Some(axis) = GamepadAxis::all().into_iter().find(|axis| {
+            gamepads
+                .iter()
+                .any(|gamepad| gamepad.get(*axis).unwrap_or(0.0).abs() > AXIS_THRESHOLD)
+        }) {
+            Some(InputBinding::GamepadAxis(axis))
+        } else {
+            continue;
+        };
+        *active = TextInputActive::Inactive;
+        let Some(data) = state_types.get(id) else {
+            continue;
+        };
+        if data.binding != new {
+            commands.queue(Perform(Edit::SetBinding {
+                state_type: id.clone(),
+                old: data.binding,
+                new,
+            }));
+        }
+    }
+}
+
+/// Sets every bound input on the running machine, right before it steps.
+///
+/// Only when the input changes, so they can still be set in the side panel in between.
+fn apply_bindings(
+    state_types: Res<StateTypes>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Query<&Gamepad>,
+    mut simulation: ResMut<Simulation>,
+    mut last_values: Local<HashMap<StateId, f32>>,
+) -> Result {
+    for data in state_types.values() {
+        let Some(binding) = &data.binding else {
+            continue;
+        };
+        let value = binding.value(&keys, &gamepads);
+        let last_value = last_values.insert(data.id.clone(), value).unwrap_or(0.0);
+        let is_held = value.abs() > AXIS_THRESHOLD;
+        let was_held = last_value.abs() > AXIS_THRESHOLD;
+        let new = match data.state_type {
+            StateType::Bool if is_held != was_held => StateTypeValue::Bool(is_held),
+            // A trigger stays set until the next step, so only set it on the press itself
+            StateType::Trigger if is_held && !was_held => StateTypeValue::Trigger(true),
+            StateType::Int if value != last_value => StateTypeValue::Int(value.round() as i64),
+            StateType::Float { .. } if value != last_value => {
+                data.state_type.coerce(StateTypeValue::Float(value))
+            }
+            StateType::Bool
+            | StateType::Trigger
+            | StateType::Int
+            | StateType::Float { .. }
+            | StateType::Enum(_) => continue,
+        };
+        if simulation.runtime.input(&data.id) != Some(&new) {
+            simulation.runtime.set_input(&data.id, new)?;
+        }
+    }
+    Ok(())
+}
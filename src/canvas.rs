@@ -0,0 +1,154 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+
+use crate::editor::MainSpace;
+
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+/// How much one line of scrolling zooms in or out.
+const ZOOM_PER_LINE: f32 = 1.1;
+const PIXELS_PER_LINE: f32 = 20.0;
+
+pub struct CanvasPlugin;
+
+impl Plugin for CanvasPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Canvas>().add_systems(
+            Update,
+            (
+                track_main_space,
+                zoom_canvas.after(track_main_space),
+                position_canvas_items.after(zoom_canvas),
+            ),
+        );
+    }
+}
+
+/// How the graph is panned and zoomed inside the [`MainSpace`].
+///
+/// Canvas coordinates are what the document stores, window coordinates are logical pixels from
+/// the top left of the window, like the cursor position.
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct Canvas {
+    /// Where the canvas origin is, relative to the top left corner of the [`MainSpace`].
+    pub pan: Vec2,
+    pub zoom: f32,
+    /// The [`MainSpace`] in window coordinates.
+    pub viewport: Rect,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self {
+            pan: Vec2::ZERO,
+            zoom: 1.0,
+            viewport: Rect::default(),
+        }
+    }
+}
+
+impl Canvas {
+    /// Scales a length on the canvas to logical pixels.
+    pub fn scale(&self, length: f32) -> f32 {
+        length * self.zoom
+    }
+
+    pub fn px(&self, length: f32) -> Val {
+        Val::Px(self.scale(length))
+    }
+
+    /// Canvas coordinates to an offset inside the [`MainSpace`].
+    pub fn to_local(&self, position: Vec2) -> Vec2 {
+        position * self.zoom + self.pan
+    }
+
+    pub fn to_window(&self, position: Vec2) -> Vec2 {
+        self.viewport.min + self.to_local(position)
+    }
+
+    pub fn from_window(&self, position: Vec2) -> Vec2 {
+        (position - self.viewport.min - self.pan) / self.zoom
+    }
+
+    /// Zooms by `factor`, keeping whatever is under `anchor` (in window coordinates) in place.
+    pub fn zoom_at(&mut self, anchor: Vec2, factor: f32) {
+        let fixed = self.from_window(anchor);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.pan += anchor - self.to_window(fixed);
+    }
+}
+
+/// Where a node sits on the [`Canvas`]. Its [`Node`] offsets follow from this and the canvas
+/// transform, so don't change those directly.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Deref, DerefMut)]
+#[require(Node, CanvasOffset)]
+pub struct CanvasPosition(pub Vec2);
+
+/// Moves a node on the [`Canvas`] without changing its [`CanvasPosition`], e.g. to draw it
+/// inside the lane of the region it's in.
+#[derive(Component, Debug, Default, Clone, Copy, PartialEq, Deref, DerefMut)]
+pub struct CanvasOffset(pub Vec2);
+
+/// The center of a UI node in window coordinates.
+pub fn ui_position(transform: &GlobalTransform, computed: &ComputedNode) -> Vec2 {
+    transform.translation().xy() * computed.inverse_scale_factor()
+}
+
+fn track_main_space(
+    main_space: Query<(&GlobalTransform, &ComputedNode), With<MainSpace>>,
+    mut canvas: ResMut<Canvas>,
+) -> Result {
+    let (transform, computed) = main_space.single()?;
+    let viewport = Rect::from_center_size(
+        ui_position(transform, computed),
+        computed.size() * computed.inverse_scale_factor(),
+    );
+    if canvas.viewport != viewport {
+        canvas.viewport = viewport;
+    }
+    Ok(())
+}
+
+fn zoom_canvas(
+    mut wheel: EventReader<MouseWheel>,
+    window: Query<&Window>,
+    mut canvas: ResMut<Canvas>,
+) -> Result {
+    let lines = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum::<f32>();
+    let Some(cursor) = window.single()?.cursor_position() else {
+        return Ok(());
+    };
+    if lines == 0.0 || !canvas.viewport.contains(cursor) {
+        return Ok(());
+    }
+    canvas.zoom_at(cursor, ZOOM_PER_LINE.powf(lines));
+    Ok(())
+}
+
+/// Pans the canvas while it's dragged with the middle mouse button, also when the drag started
+/// on top of a node.
+pub fn pan_canvas(trigger: Trigger<Pointer<Drag>>, mut canvas: ResMut<Canvas>) {
+    if trigger.button == PointerButton::Middle {
+        canvas.pan += trigger.delta;
+    }
+}
+
+fn position_canvas_items(
+    canvas: Res<Canvas>,
+    mut items: Query<(Ref<CanvasPosition>, Ref<CanvasOffset>, &mut Node)>,
+) {
+    for (position, offset, mut node) in items.iter_mut() {
+        if !canvas.is_changed() && !position.is_changed() && !offset.is_changed() {
+            continue;
+        }
+        let local = canvas.to_local(position.0 + offset.0);
+        node.left = Val::Px(local.x);
+        node.top = Val::Px(local.y);
+    }
+}
@@ -0,0 +1,770 @@
+//! Turns a [`Document`] into a Rust module that runs the same machine without this crate.
+//!
+//! The module has a `State` enum with one variant per state node without children that defaults
+//! to the initial state, an `Inputs` struct with one field per state type, and `State::step`,
+//! which behaves like [`crate::runtime::Runtime::step`] except that triggers have to be reset by
+//! the caller. States with children only show up in the transitions of the states inside them.
+//!
+//! A single enum can't be in several regions at once or remember where it was, so documents with
+//! parallel states or history pseudo-states aren't supported. Neither are timed transitions, since
+//! `State::step` doesn't know about time, or actions, since it only reads the inputs.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+
+use crate::bindings::no_bound_key_pressed;
+use crate::document::{CurrentDocument, Document, DocumentPath, DocumentState};
+use crate::guard::{CompareOp, Guard};
+use crate::text_input::no_text_input_active;
+use crate::{StateId, StateNodeId, StateType, StateTypeData, StateTypeValue};
+
+pub struct CodegenPlugin;
+
+impl Plugin for CodegenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            codegen_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+        )
+        .add_observer(export_rust);
+    }
+}
+
+/// Writes the generated module next to the [`DocumentPath`], with an `rs` extension.
+#[derive(Event, Debug, Default)]
+pub struct ExportRust;
+
+fn codegen_shortcuts(keys: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        && keys.just_pressed(KeyCode::KeyE)
+    {
+        commands.trigger(ExportRust);
+    }
+}
+
+/// Reports what keeps the document from being generated instead of writing anything.
+fn export_rust(_trigger: Trigger<ExportRust>, path: Res<DocumentPath>, current: CurrentDocument) {
+    let path = path.with_extension("rs");
+    let written =
+        generate_rust(&current.document()).and_then(|source| Ok(std::fs::write(&path, source)?));
+    match written {
+        Ok(()) => info!("Exported Rust module to {}", path.display()),
+        Err(error) => warn!("Couldn't export Rust module to {}: {error}", path.display()),
+    }
+}
+
+/// Generates a module from the document at `document` into `$OUT_DIR/<file stem>.rs`, for use
+/// in a `build.rs`:
+///
+/// ```ignore
+/// fn main() {
+///     state_machine::codegen::build_script("machines/player.ron").unwrap();
+/// }
+/// ```
+///
+/// and then `mod player { include!(concat!(env!("OUT_DIR"), "/player.rs")); }` in the crate.
+pub fn build_script(document: impl AsRef<Path>) -> Result {
+    let document = document.as_ref();
+    println!("cargo:rerun-if-changed={}", document.display());
+    let out_dir = std::env::var_os("OUT_DIR").ok_or("OUT_DIR not set, not in a build script?")?;
+    let stem = document
+        .file_stem()
+        .ok_or("Document path has no file name")?;
+    let output = Path::new(&out_dir).join(stem).with_extension("rs");
+    std::fs::write(output, generate_rust(&Document::load(document)?)?)?;
+    Ok(())
+}
+
+pub fn generate_rust(document: &Document) -> Result<String> {
+    if let Some(parallel) = document
+        .states
+        .iter()
+        .find(|state| state.parallel && document.is_composite(&state.id))
+    {
+        return Err(format!(
+            "{} is parallel, which generated code doesn't support",
+            parallel.name
+        )
+        .into());
+    }
+    if let Some(history) = document.states.iter().find(|state| state.history.is_some()) {
+        return Err(format!(
+            "{} is a history state, which generated code doesn't support",
+            history.name
+        )
+        .into());
+    }
+    let name = |id| document.state(id).map(|state| state.name.as_str());
+    if let Some(timed) = document
+        .transitions
+        .iter()
+        .find(|transition| transition.after.is_some())
+    {
+        return Err(format!(
+            "The transition from {} to {} is timed, which generated code doesn't support",
+            name(&timed.from).unwrap_or_default(),
+            name(&timed.to).unwrap_or_default()
+        )
+        .into());
+    }
+    if let Some(state) = document
+        .states
+        .iter()
+        .find(|state| !(state.on_entry.is_empty() && state.on_exit.is_empty()))
+    {
+        return Err(format!(
+            "{} has entry or exit actions, which generated code doesn't support",
+            state.name
+        )
+        .into());
+    }
+    if let Some(transition) = document
+        .transitions
+        .iter()
+        .find(|transition| !transition.actions.is_empty())
+    {
+        return Err(format!(
+            "The transition from {} to {} has actions, which generated code doesn't support",
+            name(&transition.from).unwrap_or_default(),
+            name(&transition.to).unwrap_or_default()
+        )
+        .into());
+    }
+    let names = Names::new(document);
+    let mut out = String::new();
+    // States with children are never the one the machine is in, only the ones around it
+    let leaves = document
+        .states
+        .iter()
+        .filter(|state| !document.is_composite(&state.id))
+        .collect::<Vec<_>>();
+    let finished = |state: &DocumentState| {
+        state.terminal || document.ancestors(&state.id).iter().any(|a| a.terminal)
+    };
+
+    writeln!(
+        out,
+        "// Generated from a state machine document, don't edit by hand."
+    )?;
+    writeln!(out)?;
+    writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]")?;
+    writeln!(out, "pub enum State {{")?;
+    for state in leaves.iter() {
+        writeln!(out, "    {},", names.states[&state.id])?;
+    }
+    writeln!(out, "}}")?;
+    if let Some(initial) = document
+        .initial_state()
+        .and_then(|initial| document.entry_paths(&initial.id).first()?.last().copied())
+    {
+        writeln!(out)?;
+        writeln!(out, "impl Default for State {{")?;
+        writeln!(out, "    fn default() -> Self {{")?;
+        writeln!(out, "        State::{}", names.states[&initial.id])?;
+        writeln!(out, "    }}")?;
+        writeln!(out, "}}")?;
+    }
+
+    for data in document.state_types.iter() {
+        let StateType::Enum(variants) = &data.state_type else {
+            continue;
+        };
+        let (type_name, variant_names) = &names.enums[&data.id];
+        writeln!(out)?;
+        writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]")?;
+        writeln!(out, "pub enum {type_name} {{")?;
+        for variant in variants {
+            writeln!(out, "    {},", variant_names[variant])?;
+        }
+        writeln!(out, "}}")?;
+    }
+
+    writeln!(out)?;
+    writeln!(
+        out,
+        "/// Triggers are only meant to be set for a single step."
+    )?;
+    writeln!(out, "#[derive(Debug, Clone, PartialEq)]")?;
+    writeln!(out, "pub struct Inputs {{")?;
+    for data in document.state_types.iter() {
+        writeln!(
+            out,
+            "    pub {}: {},",
+            names.fields[&data.id],
+            names.field_type(data)
+        )?;
+    }
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl Default for Inputs {{")?;
+    writeln!(out, "    fn default() -> Self {{")?;
+    writeln!(out, "        Self {{")?;
+    for data in document.state_types.iter() {
+        writeln!(
+            out,
+            "            {}: {},",
+            names.fields[&data.id],
+            names.literal(&data.id, &data.state_type.default_value())?
+        )?;
+    }
+    writeln!(out, "        }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    // Each state's transitions, in the order the runtime tries them: its own, then those of the
+    // states it's nested in, each once per way into the target
+    let mut arms = vec![];
+    for state in leaves.iter().copied() {
+        let mut branches = vec![];
+        let sources = [state]
+            .into_iter()
+            .chain(document.ancestors(&state.id))
+            .filter(|_| !finished(state));
+        for source in sources {
+            for transition in document
+                .transitions
+                .iter()
+                .filter(|transition| transition.from == source.id)
+            {
+                for path in document.entry_paths(&transition.to) {
+                    let mut conditions = vec![];
+                    if let Some(guard) = &transition.guard {
+                        conditions.push(names.guard(guard, Precedence::And));
+                    }
+                    for entered in document
+                        .ancestors(&transition.to)
+                        .into_iter()
+                        .chain(path.iter().copied())
+                    {
+                        for condition in names.matches(entered) {
+                            if !conditions.contains(&condition) {
+                                conditions.push(condition);
+                            }
+                        }
+                    }
+                    let condition = (!conditions.is_empty()).then(|| conditions.join(" && "));
+                    let target = path.last().ok_or("Transition target not found")?;
+                    branches.push((condition, &names.states[&target.id]));
+                }
+            }
+        }
+        arms.push((&names.states[&state.id], branches));
+    }
+    let branches = arms.iter().flat_map(|(_, branches)| branches);
+    let reads_inputs = branches
+        .clone()
+        .any(|(condition, _)| condition.as_ref().is_some_and(|c| c.contains("inputs.")));
+    let transitions = branches.count() > 0;
+
+    let terminal = leaves
+        .iter()
+        .filter(|state| finished(state))
+        .map(|state| format!("State::{}", names.states[&state.id]))
+        .collect::<Vec<_>>();
+
+    writeln!(out)?;
+    writeln!(out, "impl State {{")?;
+    writeln!(out, "    /// Whether the machine stops here.")?;
+    writeln!(out, "    pub fn is_terminal(&self) -> bool {{")?;
+    if terminal.is_empty() {
+        writeln!(out, "        false")?;
+    } else {
+        writeln!(out, "        matches!(self, {})", terminal.join(" | "))?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "    /// Takes the first transition whose guard holds and whose target matches the inputs,"
+    )?;
+    writeln!(out, "    /// returning whether there was one.")?;
+    writeln!(
+        out,
+        "    pub fn step(&mut self, {}: &Inputs) -> bool {{",
+        if reads_inputs { "inputs" } else { "_inputs" }
+    )?;
+    if !transitions {
+        writeln!(out, "        false")?;
+    } else {
+        writeln!(out, "        let next = match *self {{")?;
+        for (state, branches) in arms {
+            write!(out, "            State::{state} => ")?;
+            write_branches(&mut out, &branches)?;
+        }
+        writeln!(out, "        }};")?;
+        writeln!(out, "        *self = next;")?;
+        writeln!(out, "        true")?;
+    }
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+
+    Ok(out)
+}
+
+fn write_branches(out: &mut String, branches: &[(Option<String>, &String)]) -> Result {
+    if let Some((None, target)) = branches.first() {
+        writeln!(out, "State::{target},")?;
+        return Ok(());
+    }
+    if branches.is_empty() {
+        writeln!(out, "return false,")?;
+        return Ok(());
+    }
+    writeln!(out, "{{")?;
+    let mut closed = false;
+    for (index, (condition, target)) in branches.iter().enumerate() {
+        let keyword = if index == 0 { "if" } else { "} else if" };
+        match condition {
+            Some(condition) => {
+                writeln!(out, "                {keyword} {condition} {{")?;
+                writeln!(out, "                    State::{target}")?;
+            }
+            // Always taken, so nothing after it can be
+            None => {
+                writeln!(out, "                }} else {{")?;
+                writeln!(out, "                    State::{target}")?;
+                closed = true;
+                break;
+            }
+        }
+    }
+    if !closed {
+        writeln!(out, "                }} else {{")?;
+        writeln!(out, "                    return false;")?;
+    }
+    writeln!(out, "                }}")?;
+    writeln!(out, "            }}")?;
+    Ok(())
+}
+
+/// How tightly an expression binds, so parentheses are only added where Rust needs them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Or,
+    And,
+    Compare,
+    Unary,
+}
+
+/// Rust identifiers for everything in the document, unique and free of keywords.
+struct Names<'a> {
+    state_types: &'a [StateTypeData],
+    states: HashMap<StateNodeId, String>,
+    fields: HashMap<StateId, String>,
+    /// Type name and variant names of every enum state type.
+    enums: HashMap<StateId, (String, HashMap<String, String>)>,
+}
+
+impl<'a> Names<'a> {
+    fn new(document: &'a Document) -> Self {
+        let mut types = HashSet::from(["State".to_string(), "Inputs".to_string()]);
+        let mut variants = HashSet::new();
+        let states = document
+            .states
+            .iter()
+            .map(|state| {
+                let name = unique(&mut variants, upper_camel_case(&state.name, "State"));
+                (state.id.clone(), name)
+            })
+            .collect();
+
+        let mut fields = HashSet::new();
+        let mut names = Self {
+            state_types: &document.state_types,
+            states,
+            fields: HashMap::default(),
+            enums: HashMap::default(),
+        };
+        for data in document.state_types.iter() {
+            names.fields.insert(
+                data.id.clone(),
+                unique(&mut fields, snake_case(&data.name, "input")),
+            );
+            if let StateType::Enum(enum_variants) = &data.state_type {
+                let type_name = unique(&mut types, upper_camel_case(&data.name, "Input"));
+                let mut used = HashSet::new();
+                let variant_names = enum_variants
+                    .iter()
+                    .map(|variant| {
+                        let name = unique(&mut used, upper_camel_case(variant, "Variant"));
+                        (variant.clone(), name)
+                    })
+                    .collect();
+                names
+                    .enums
+                    .insert(data.id.clone(), (type_name, variant_names));
+            }
+        }
+        names
+    }
+
+    fn data(&self, state_type: &StateId) -> Option<&StateTypeData> {
+        self.state_types.iter().find(|data| data.id == *state_type)
+    }
+
+    fn field_type(&self, data: &StateTypeData) -> String {
+        match &data.state_type {
+            StateType::Bool | StateType::Trigger => "bool".to_string(),
+            StateType::Int => "i64".to_string(),
+            StateType::Float { .. } => "f32".to_string(),
+            StateType::Enum(_) => self.enums[&data.id].0.clone(),
+        }
+    }
+
+    /// A value of `state_type`, or `None` if it isn't one of its enum's variants.
+    fn enum_literal(&self, state_type: &StateId, variant: &str) -> Option<String> {
+        let (type_name, variants) = self.enums.get(state_type)?;
+        Some(format!("{type_name}::{}", variants.get(variant)?))
+    }
+
+    fn literal(&self, state_type: &StateId, value: &StateTypeValue) -> Result<String> {
+        Ok(match value {
+            StateTypeValue::Bool(value) | StateTypeValue::Trigger(value) => value.to_string(),
+            StateTypeValue::Int(value) => value.to_string(),
+            StateTypeValue::Float(value) => float_literal(*value),
+            StateTypeValue::Enum(variant) => self
+                .enum_literal(state_type, variant)
+                .ok_or_else(|| format!("{variant:?} is not a variant of its enum"))?,
+        })
+    }
+
+    /// One condition per value `state` assigns, all of which have to hold to enter it.
+    fn matches(&self, state: &DocumentState) -> Vec<String> {
+        state
+            .state
+            .iter()
+            .filter(|value| !value.any)
+            .map(|value| {
+                self.compare(&value.state, CompareOp::Eq, &value.value, true)
+                    .1
+            })
+            .collect()
+    }
+
+    fn guard(&self, guard: &Guard, context: Precedence) -> String {
+        let (precedence, text) = match guard {
+            Guard::Literal(value) => (Precedence::Unary, value.to_string()),
+            Guard::Variable(variable) => match self.data(variable) {
+                Some(data) if matches!(data.state_type, StateType::Bool | StateType::Trigger) => (
+                    Precedence::Unary,
+                    format!("inputs.{}", self.fields[variable]),
+                ),
+                _ => (Precedence::Unary, "false".to_string()),
+            },
+            Guard::Compare {
+                variable,
+                op,
+                value,
+            } => self.compare(variable, *op, value, false),
+            Guard::Not(guard) => (
+                Precedence::Unary,
+                format!("!{}", self.guard(guard, Precedence::Unary)),
+            ),
+            Guard::And(a, b) => (
+                Precedence::And,
+                format!(
+                    "{} && {}",
+                    self.guard(a, Precedence::And),
+                    self.guard(b, Precedence::And)
+                ),
+            ),
+            Guard::Or(a, b) => (
+                Precedence::Or,
+                format!(
+                    "{} || {}",
+                    self.guard(a, Precedence::Or),
+                    self.guard(b, Precedence::Or)
+                ),
+            ),
+        };
+        if precedence < context {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+
+    /// Mirrors [`crate::guard::compare`]. `strict` compares like the runtime checks the values
+    /// of a target state, where a trigger and a bool are never equal.
+    fn compare(
+        &self,
+        variable: &StateId,
+        op: CompareOp,
+        value: &StateTypeValue,
+        strict: bool,
+    ) -> (Precedence, String) {
+        let constant = |holds: bool| (Precedence::Unary, holds.to_string());
+        let mismatch = constant(op == CompareOp::Ne);
+        let Some(data) = self.data(variable) else {
+            // The runtime has no input for it, so nothing about it holds
+            return constant(false);
+        };
+        let field = format!("inputs.{}", self.fields[variable]);
+        let comparison = |left: String, right: String| {
+            (
+                Precedence::Compare,
+                format!("{left} {} {right}", op.symbol()),
+            )
+        };
+        match (&data.state_type, value) {
+            (StateType::Bool, StateTypeValue::Trigger(_))
+            | (StateType::Trigger, StateTypeValue::Bool(_))
+                if strict =>
+            {
+                mismatch
+            }
+            (
+                StateType::Bool | StateType::Trigger,
+                StateTypeValue::Bool(expected) | StateTypeValue::Trigger(expected),
+            ) => match op {
+                CompareOp::Eq | CompareOp::Ne if *expected == (op == CompareOp::Eq) => {
+                    (Precedence::Unary, field)
+                }
+                CompareOp::Eq | CompareOp::Ne => (Precedence::Unary, format!("!{field}")),
+                _ => constant(false),
+            },
+            (StateType::Int, StateTypeValue::Int(value)) => comparison(field, value.to_string()),
+            (StateType::Int, StateTypeValue::Float(value)) if !strict => {
+                comparison(format!("({field} as f32)"), float_literal(*value))
+            }
+            (StateType::Float { .. }, StateTypeValue::Float(value)) => {
+                comparison(field, float_literal(*value))
+            }
+            (StateType::Float { .. }, StateTypeValue::Int(value)) if !strict => {
+                comparison(field, float_literal(*value as f32))
+            }
+            (StateType::Enum(_), StateTypeValue::Enum(variant)) => {
+                match (op, self.enum_literal(variable, variant)) {
+                    (CompareOp::Eq | CompareOp::Ne, Some(literal)) => comparison(field, literal),
+                    (CompareOp::Eq | CompareOp::Ne, None) => mismatch,
+                    _ => constant(false),
+                }
+            }
+            _ if op.is_ordering() => constant(false),
+            _ => mismatch,
+        }
+    }
+}
+
+fn float_literal(value: f32) -> String {
+    if value.is_nan() {
+        "f32::NAN".to_string()
+    } else if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        format!("{sign}f32::INFINITY")
+    } else {
+        format!("{value:?}")
+    }
+}
+
+/// ASCII alphanumeric runs of `name`, which is free-form text typed into the editor.
+fn words(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+fn upper_camel_case(name: &str, prefix: &str) -> String {
+    let name = words(name)
+        .map(|word| {
+            let (first, rest) = word.split_at(1);
+            first.to_ascii_uppercase() + rest
+        })
+        .collect::<String>();
+    with_prefix(name, prefix, |name| name == "Self")
+}
+
+fn snake_case(name: &str, prefix: &str) -> String {
+    let name = words(name)
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("_");
+    with_prefix(name, prefix, |name| RUST_KEYWORDS.contains(&name))
+}
+
+/// Prefixes names that can't be identifiers on their own, e.g. because they start with a digit.
+fn with_prefix(name: String, prefix: &str, is_keyword: impl Fn(&str) -> bool) -> String {
+    if name.is_empty() {
+        prefix.to_string()
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) || is_keyword(&name) {
+        let separator = if prefix.starts_with(char::is_lowercase) {
+            "_"
+        } else {
+            ""
+        };
+        format!("{prefix}{separator}{name}")
+    } else {
+        name
+    }
+}
+
+fn unique(used: &mut HashSet<String>, name: String) -> String {
+    let separator = if name.starts_with(char::is_lowercase) {
+        "_"
+    } else {
+        ""
+    };
+    let name = (1..)
+        .map(|n| match n {
+            1 => name.clone(),
+            n => format!("{name}{separator}{n}"),
+        })
+        .find(|candidate| !used.contains(candidate))
+        .unwrap();
+    used.insert(name.clone());
+    name
+}
+
+const RUST_KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "union", "unsafe", "unsized", "use", "virtual", "where", "while",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::document::fixtures::{document, guarded, state, transition};
+    use crate::{HistoryKind, Transition};
+
+    #[test]
+    fn flat_machine() {
+        let state_types = vec![
+            StateTypeData::new("Jump", StateType::Trigger),
+            StateTypeData::new(
+                "Speed",
+                StateType::Float {
+                    min: None,
+                    max: None,
+                },
+            ),
+        ];
+        let idle = state("Idle", None, true);
+        let mut air = state("In air", None, false);
+        air.terminal = true;
+        let jumps = guarded(&idle, &air, "Jump and Speed > 1", &state_types);
+        let source = generate_rust(&document(state_types, &[&idle, &air], vec![jumps])).unwrap();
+        for expected in [
+            "pub enum State {\n    Idle,\n    InAir,\n}",
+            "    fn default() -> Self {\n        State::Idle\n    }",
+            "pub struct Inputs {\n    pub jump: bool,\n    pub speed: f32,\n}",
+            "            jump: false,\n            speed: 0.0,\n",
+            "        matches!(self, State::InAir)",
+            "            State::Idle => {
+                if inputs.jump && inputs.speed > 1.0 {
+                    State::InAir
+                } else {
+                    return false;
+                }
+            }
+            State::InAir => return false,
+",
+        ] {
+            assert!(source.contains(expected), "{expected}\nnot in\n{source}");
+        }
+    }
+
+    #[test]
+    fn nested_states_enter_their_initial_child_and_inherit_transitions() {
+        let state_types = vec![
+            StateTypeData::new("Go", StateType::Trigger),
+            StateTypeData::new("Leave", StateType::Bool),
+        ];
+        let parent = state("Parent", None, true);
+        let first = state("First", Some(&parent), true);
+        let second = state("Second", Some(&parent), false);
+        let out = state("Out", None, false);
+        let transitions = vec![
+            guarded(&first, &second, "Go", &state_types),
+            guarded(&parent, &out, "Leave", &state_types),
+            transition(&out, &parent),
+        ];
+        let source = generate_rust(&document(
+            state_types,
+            &[&parent, &first, &second, &out],
+            transitions,
+        ))
+        .unwrap();
+        for expected in [
+            "pub enum State {\n    First,\n    Second,\n    Out,\n}",
+            "    fn default() -> Self {\n        State::First\n    }",
+            "            State::First => {
+                if inputs.go {
+                    State::Second
+                } else if inputs.leave {
+                    State::Out
+                } else {
+                    return false;
+                }
+            }
+            State::Second => {
+                if inputs.leave {
+                    State::Out
+                } else {
+                    return false;
+                }
+            }
+            State::Out => State::First,
+",
+        ] {
+            assert!(source.contains(expected), "{expected}\nnot in\n{source}");
+        }
+    }
+
+    #[test]
+    fn unsupported_features_are_errors() {
+        // Without the backtrace Bevy adds to errors
+        let error = |document: &Document| {
+            let error = generate_rust(document).unwrap_err().to_string();
+            error.lines().next().unwrap_or_default().to_string()
+        };
+
+        let mut both = state("Both", None, true);
+        both.parallel = true;
+        let left = state("Left", Some(&both), true);
+        assert_eq!(
+            error(&document(vec![], &[&both, &left], vec![])),
+            "Both is parallel, which generated code doesn't support"
+        );
+
+        let parent = state("Parent", None, true);
+        let mut history = state("Back", Some(&parent), false);
+        history.history = Some(HistoryKind::Shallow);
+        assert_eq!(
+            error(&document(vec![], &[&parent, &history], vec![])),
+            "Back is a history state, which generated code doesn't support"
+        );
+
+        let start = state("Start", None, true);
+        let end = state("End", None, false);
+        let timed = Transition {
+            after: Some(1.0),
+            ..transition(&start, &end)
+        };
+        assert_eq!(
+            error(&document(vec![], &[&start, &end], vec![timed])),
+            "The transition from Start to End is timed, which generated code doesn't support"
+        );
+
+        let mut noisy = start.clone();
+        noisy.on_exit.push(Action::Emit("Left".into()));
+        assert_eq!(
+            error(&document(vec![], &[&noisy, &end], vec![])),
+            "Start has entry or exit actions, which generated code doesn't support"
+        );
+
+        let acting = Transition {
+            actions: vec![Action::Emit("Moved".into())],
+            ..transition(&start, &end)
+        };
+        assert_eq!(
+            error(&document(vec![], &[&start, &end], vec![acting])),
+            "The transition from Start to End has actions, which generated code doesn't support"
+        );
+    }
+}
@@ -0,0 +1,390 @@
+//! A truth table of every combination of the bool and enum variables, and which states match
+//! each one.
+
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+
+use crate::bindings::no_bound_key_pressed;
+use crate::document::{CurrentDocument, Document};
+use crate::editor::MainSpace;
+use crate::history::History;
+use crate::text_input::no_text_input_active;
+use crate::{StateId, StateNodeId, StateType, StateTypeValue};
+
+/// More rows than this aren't worth reading, so the table stops there.
+pub const MAX_ROWS: usize = 256;
+
+pub struct CoveragePlugin;
+
+impl Plugin for CoveragePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                coverage_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+                update_coverage_table,
+            ),
+        )
+        .add_observer(add_coverage_table);
+    }
+}
+
+/// Which states match every combination of the variables that only have a few values.
+///
+/// Ints and floats are left out, so a state that only differs from another by a number overlaps
+/// with it here. So are triggers, which are only set until the next step rather than telling
+/// states apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub variables: Vec<StateId>,
+    pub rows: Vec<CoverageRow>,
+    /// Whether there were more than [`MAX_ROWS`] combinations.
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CoverageRow {
+    /// One per [`Coverage::variables`].
+    pub values: Vec<StateTypeValue>,
+    pub states: Vec<StateNodeId>,
+    /// Whether two of the states can't be active at once, see [`Document::exclusive`]. States in
+    /// different regions of a parallel state match side by side without overlapping.
+    pub overlap: bool,
+}
+
+impl CoverageRow {
+    /// No state matches.
+    pub fn is_gap(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// More than one state matches, so the machine can't tell which one it's in.
+    pub fn is_overlap(&self) -> bool {
+        self.overlap
+    }
+}
+
+impl Coverage {
+    pub fn gaps(&self) -> usize {
+        self.rows.iter().filter(|row| row.is_gap()).count()
+    }
+
+    pub fn overlaps(&self) -> usize {
+        self.rows.iter().filter(|row| row.is_overlap()).count()
+    }
+}
+
+pub fn coverage(document: &Document) -> Coverage {
+    let (variables, domains): (Vec<_>, Vec<_>) = document
+        .state_types
+        .iter()
+        .filter_map(|data| {
+            let domain = match &data.state_type {
+                StateType::Bool => vec![StateTypeValue::Bool(false), StateTypeValue::Bool(true)],
+                StateType::Enum(variants) if !variants.is_empty() => variants
+                    .iter()
+                    .map(|variant| StateTypeValue::Enum(variant.clone()))
+                    .collect(),
+                _ => return None,
+            };
+            Some((data.id.clone(), domain))
+        })
+        .unzip();
+
+    let mut rows = vec![];
+    let mut indices = vec![0; variables.len()];
+    let truncated = loop {
+        let values = indices
+            .iter()
+            .zip(domains.iter())
+            .map(|(index, domain)| domain[*index].clone())
+            .collect::<Vec<_>>();
+        // States that don't assign a variable match any value of it. The machine is only ever in
+        // states without children, which also have to match what the states around them assign.
+        let states = document
+            .states
+            .iter()
+            .filter(|state| !document.is_composite(&state.id) && state.history.is_none())
+            .filter(|state| {
+                [*state]
+                    .into_iter()
+                    .chain(document.ancestors(&state.id))
+                    .all(|state| {
+                        variables
+                            .iter()
+                            .zip(values.iter())
+                            .all(|(variable, value)| {
+                                state
+                                    .state
+                                    .iter()
+                                    .find(|assigned| assigned.state == *variable)
+                                    .is_none_or(|assigned| assigned.matches(value))
+                            })
+                    })
+            })
+            .map(|state| state.id.clone())
+            .collect::<Vec<_>>();
+        let overlap = states.iter().enumerate().any(|(index, state)| {
+            states[..index]
+                .iter()
+                .any(|other| document.exclusive(state, other))
+        });
+        rows.push(CoverageRow {
+            values,
+            states,
+            overlap,
+        });
+        // The last variable changes fastest, like a written out truth table
+        let Some(position) = indices
+            .iter()
+            .zip(domains.iter())
+            .rposition(|(index, domain)| index + 1 < domain.len())
+        else {
+            break false;
+        };
+        if rows.len() == MAX_ROWS {
+            break true;
+        }
+        indices[position] += 1;
+        indices[position + 1..].fill(0);
+    };
+
+    Coverage {
+        variables,
+        rows,
+        truncated,
+    }
+}
+
+/// Shown and hidden with `T`, filled in by [`update_coverage_table`].
+#[derive(Component)]
+struct CoverageTable;
+
+fn add_coverage_table(trigger: Trigger<OnAdd, MainSpace>, mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            top: Val::Px(10.0),
+            max_height: Val::Percent(50.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            flex_direction: FlexDirection::Column,
+            overflow: Overflow::clip(),
+            ..default()
+        },
+        BackgroundColor(css::DARK_GRAY.with_alpha(0.9).into()),
+        BorderRadius::all(Val::Px(5.0)),
+        FocusPolicy::Block,
+        ZIndex(1),
+        Visibility::Hidden,
+        CoverageTable,
+        ChildOf(trigger.target()),
+    ));
+}
+
+fn coverage_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut tables: Query<&mut Visibility, With<CoverageTable>>,
+) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if !keys.just_pressed(KeyCode::KeyT) {
+        return;
+    }
+    for mut visibility in tables.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn update_coverage_table(
+    history: Res<History>,
+    tables: Query<(Entity, Ref<Visibility>), With<CoverageTable>>,
+    current: CurrentDocument,
+    mut commands: Commands,
+) {
+    for (table, visibility) in tables.iter() {
+        // Only kept up to date while it's shown
+        if *visibility == Visibility::Hidden || !(history.is_changed() || visibility.is_changed()) {
+            continue;
+        }
+        let document = current.document();
+        let coverage = coverage(&document);
+        commands.entity(table).despawn_related::<Children>();
+
+        let summary = match (coverage.gaps(), coverage.overlaps()) {
+            (0, 0) if !coverage.truncated => "Every combination matches one state".to_string(),
+            (gaps, overlaps) => format!(
+                "{gaps} gaps, {overlaps} overlaps{}",
+                if coverage.truncated {
+                    format!(" in the first {MAX_ROWS} combinations")
+                } else {
+                    String::new()
+                }
+            ),
+        };
+        commands.spawn((
+            Text(summary),
+            TextFont::from_font_size(14.0),
+            Node {
+                margin: UiRect::bottom(Val::Px(5.0)),
+                ..default()
+            },
+            ChildOf(table),
+        ));
+
+        let grid = commands
+            .spawn((
+                Node {
+                    display: Display::Grid,
+                    grid_template_columns: vec![GridTrack::auto(); coverage.variables.len() + 1],
+                    column_gap: Val::Px(1.0),
+                    row_gap: Val::Px(1.0),
+                    ..default()
+                },
+                ChildOf(table),
+            ))
+            .id();
+        let mut cell = |text: String, color: Color| {
+            commands.spawn((
+                Node {
+                    padding: UiRect::horizontal(Val::Px(4.0)),
+                    ..default()
+                },
+                BackgroundColor(color),
+                ChildOf(grid),
+                children![(Text(text), TextFont::from_font_size(12.0))],
+            ));
+        };
+
+        let header = Color::from(css::BLACK.with_alpha(0.5));
+        for variable in coverage.variables.iter() {
+            let name = document
+                .state_types
+                .iter()
+                .find(|data| data.id == *variable)
+                .map_or(String::new(), |data| data.name.clone());
+            cell(name, header);
+        }
+        cell("State".to_string(), header);
+
+        for row in coverage.rows.iter() {
+            let color = Color::from(if row.is_gap() {
+                css::DARK_RED
+            } else if row.is_overlap() {
+                css::DARK_ORANGE
+            } else {
+                css::DARK_SLATE_GRAY
+            });
+            for value in row.values.iter() {
+                cell(value.to_string(), color);
+            }
+            let states = if row.is_gap() {
+                "none".to_string()
+            } else {
+                row.states
+                    .iter()
+                    .filter_map(|id| document.state(id))
+                    .map(|state| state.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            cell(states, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StateTypeData;
+    use crate::document::fixtures::{assigning, document, state};
+
+    fn names<'a>(document: &'a Document, row: &CoverageRow) -> Vec<&'a str> {
+        row.states
+            .iter()
+            .filter_map(|id| document.state(id))
+            .map(|state| state.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn finds_gaps_and_overlaps() {
+        let door_open = StateTypeData::new("DoorOpen", StateType::Bool);
+        let light = StateTypeData::new(
+            "Light",
+            StateType::Enum(vec!["Red".to_string(), "Green".to_string()]),
+        );
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let red = || StateTypeValue::Enum("Red".to_string());
+        let open = assigning("Open", &door_open, StateTypeValue::Bool(true));
+        let closed = assigning("Closed", &door_open, StateTypeValue::Bool(false));
+        let mut stopped = assigning("Stopped", &light, red());
+        stopped.parent = Some(closed.id.clone());
+        stopped.initial = true;
+        let lit = assigning("Lit", &light, red());
+        let document = document(
+            vec![door_open.clone(), light.clone(), go],
+            &[&open, &closed, &stopped, &lit],
+            vec![],
+        );
+
+        let coverage = coverage(&document);
+        assert_eq!(coverage.variables, [door_open.id, light.id]);
+        let rows = coverage
+            .rows
+            .iter()
+            .map(|row| {
+                let values = row
+                    .values
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>();
+                (
+                    values,
+                    names(&document, row),
+                    row.is_gap(),
+                    row.is_overlap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let row = |values: [&str; 2], states: Vec<&'static str>, gap, overlap| {
+            (values.map(str::to_string).to_vec(), states, gap, overlap)
+        };
+        assert_eq!(
+            rows,
+            [
+                row(["false", "Red"], vec!["Stopped", "Lit"], false, true),
+                row(["false", "Green"], vec![], true, false),
+                row(["true", "Red"], vec!["Open", "Lit"], false, true),
+                row(["true", "Green"], vec!["Open"], false, false),
+            ]
+        );
+        assert_eq!((coverage.gaps(), coverage.overlaps()), (1, 2));
+        assert!(!coverage.truncated);
+    }
+
+    #[test]
+    fn stops_after_max_rows() {
+        let bools = |count| {
+            (0..count)
+                .map(|index| StateTypeData::new(format!("B{index}"), StateType::Bool))
+                .collect::<Vec<_>>()
+        };
+        let only = state("Only", None, true);
+
+        // 2^8 combinations just fit
+        let coverage_of_8 = coverage(&document(bools(8), &[&only], vec![]));
+        assert_eq!(coverage_of_8.rows.len(), MAX_ROWS);
+        assert!(!coverage_of_8.truncated);
+
+        let coverage_of_9 = coverage(&document(bools(9), &[&only], vec![]));
+        assert_eq!(coverage_of_9.rows.len(), MAX_ROWS);
+        assert!(coverage_of_9.truncated);
+        assert_eq!(coverage_of_9.gaps(), 0);
+    }
+}
@@ -0,0 +1,462 @@
+use std::path::PathBuf;
+
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::action::Action;
+use crate::bindings::no_bound_key_pressed;
+use crate::canvas::CanvasPosition;
+use crate::editor::{MainSpace, StateTypeAdded, StateTypeRemoved, spawn_state_node};
+use crate::text_input::no_text_input_active;
+use crate::{HistoryKind, State, StateNodeId, StateTypeData, StateTypes, StateValue, Transition};
+
+/// Bumped whenever the on-disk layout changes in a way older builds can't read.
+pub const DOCUMENT_VERSION: u32 = 2;
+
+pub struct DocumentPlugin;
+
+impl Plugin for DocumentPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DocumentPath>()
+            .add_systems(
+                Update,
+                document_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+            )
+            .add_observer(save_document)
+            .add_observer(load_document);
+    }
+}
+
+/// Where [`SaveDocument`] writes to and [`LoadDocument`] reads from.
+#[derive(Resource, Debug, Clone, Deref, DerefMut)]
+pub struct DocumentPath(pub PathBuf);
+
+impl Default for DocumentPath {
+    fn default() -> Self {
+        Self(PathBuf::from("machine.ron"))
+    }
+}
+
+#[derive(Event, Debug, Default)]
+pub struct SaveDocument;
+
+#[derive(Event, Debug, Default)]
+pub struct LoadDocument;
+
+/// Triggered once a [`LoadDocument`] replaced everything in the editor.
+#[derive(Event, Debug, Default)]
+pub struct DocumentLoaded;
+
+/// Serializable snapshot of everything the editor knows about a state machine.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Document {
+    pub version: u32,
+    pub state_types: Vec<StateTypeData>,
+    pub states: Vec<DocumentState>,
+    pub transitions: Vec<Transition>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DocumentState {
+    pub id: StateNodeId,
+    pub name: String,
+    pub position: Vec2,
+    pub state: Vec<StateValue>,
+    #[serde(default)]
+    pub parent: Option<StateNodeId>,
+    #[serde(default)]
+    pub parallel: bool,
+    #[serde(default)]
+    pub history: Option<HistoryKind>,
+    #[serde(default)]
+    pub initial: bool,
+    #[serde(default)]
+    pub terminal: bool,
+    #[serde(default)]
+    pub on_entry: Vec<Action>,
+    #[serde(default)]
+    pub on_exit: Vec<Action>,
+}
+
+impl Document {
+    pub fn to_ron(&self) -> Result<String> {
+        Ok(ron::ser::to_string_pretty(
+            self,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(source: &str) -> Result<Self> {
+        let document: Self = ron::from_str(source)?;
+        if document.version != DOCUMENT_VERSION {
+            return Err(format!(
+                "Unsupported document version {} (expected {})",
+                document.version, DOCUMENT_VERSION
+            )
+            .into());
+        }
+        for transition in document.transitions.iter() {
+            if document.state(&transition.from).is_none()
+                || document.state(&transition.to).is_none()
+            {
+                return Err(format!(
+                    "Transition {:?} -> {:?} refers to a missing state",
+                    transition.from, transition.to
+                )
+                .into());
+            }
+        }
+        for state in document.states.iter() {
+            let Some(parent) = &state.parent else {
+                continue;
+            };
+            if document.state(parent).is_none() {
+                return Err(format!("State {} is nested in a missing state", state.name).into());
+            }
+            if document.ancestors(&state.id).len() == document.states.len() {
+                return Err(format!("State {} is nested in itself", state.name).into());
+            }
+        }
+        // Regions of a parallel state all start at once, so their initial markers are unused
+        for parent in document
+            .states
+            .iter()
+            .filter(|state| !state.parallel)
+            .map(|state| Some(&state.id))
+            .chain([None])
+        {
+            if document
+                .children(parent)
+                .filter(|state| state.initial)
+                .count()
+                > 1
+            {
+                return Err("More than one initial state".into());
+            }
+        }
+        Ok(document)
+    }
+
+    pub fn state(&self, id: &StateNodeId) -> Option<&DocumentState> {
+        self.states.iter().find(|state| state.id == *id)
+    }
+
+    pub fn state_named(&self, name: &str) -> Option<&DocumentState> {
+        self.states.iter().find(|state| state.name == name)
+    }
+
+    /// The top level state the machine starts in.
+    pub fn initial_state(&self) -> Option<&DocumentState> {
+        self.children(None).find(|state| state.initial)
+    }
+
+    /// The states nested directly in `parent`, or the top level ones for `None`. History
+    /// pseudo-states are left out, as the machine is never in them, see
+    /// [`Document::history_states`].
+    pub fn children<'a>(
+        &'a self,
+        parent: Option<&'a StateNodeId>,
+    ) -> impl Iterator<Item = &'a DocumentState> {
+        self.states
+            .iter()
+            .filter(move |state| state.parent.as_ref() == parent && state.history.is_none())
+    }
+
+    /// The history pseudo-states nested directly in `parent`.
+    pub fn history_states<'a>(
+        &'a self,
+        parent: Option<&'a StateNodeId>,
+    ) -> impl Iterator<Item = &'a DocumentState> {
+        self.states
+            .iter()
+            .filter(move |state| state.parent.as_ref() == parent && state.history.is_some())
+    }
+
+    /// Whether other states are nested in `id`.
+    pub fn is_composite(&self, id: &StateNodeId) -> bool {
+        self.children(Some(id)).next().is_some()
+    }
+
+    /// The states `id` is nested in, innermost first.
+    pub fn ancestors(&self, id: &StateNodeId) -> Vec<&DocumentState> {
+        let mut ancestors = vec![];
+        let mut parent = self.state(id).and_then(|state| state.parent.as_ref());
+        // Stops at the number of states in case a broken document nests a state in itself
+        while let Some(state) = parent.and_then(|parent| self.state(parent))
+            && ancestors.len() < self.states.len()
+        {
+            ancestors.push(state);
+            parent = state.parent.as_ref();
+        }
+        ancestors
+    }
+
+    /// Whether the machine can't be in both states at once: they're different, neither is nested
+    /// in the other, and the innermost state containing both isn't parallel.
+    pub fn exclusive(&self, a: &StateNodeId, b: &StateNodeId) -> bool {
+        let a_ancestors = self.ancestors(a);
+        let b_ancestors = self.ancestors(b);
+        if a == b
+            || a_ancestors.iter().any(|state| state.id == *b)
+            || b_ancestors.iter().any(|state| state.id == *a)
+        {
+            return false;
+        }
+        a_ancestors
+            .iter()
+            .find(|state| b_ancestors.iter().any(|other| other.id == state.id))
+            .is_none_or(|shared| !shared.parallel)
+    }
+
+    /// Every way into `id`: the states entered, from `id` down to one without children. Paths
+    /// through initial children come first, the rest follow in document order.
+    ///
+    /// The regions of a parallel state count as alternatives here, while the
+    /// [`crate::runtime::Runtime`] enters all of them.
+    pub fn entry_paths(&self, id: &StateNodeId) -> Vec<Vec<&DocumentState>> {
+        let Some(state) = self.state(id) else {
+            return vec![];
+        };
+        let mut children = self.children(Some(id)).collect::<Vec<_>>();
+        if children.is_empty() {
+            return vec![vec![state]];
+        }
+        children.sort_by_key(|child| !child.initial);
+        children
+            .iter()
+            .flat_map(|child| self.entry_paths(&child.id))
+            .map(|path| [state].into_iter().chain(path).collect())
+            .collect()
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result {
+        std::fs::write(path, self.to_ron()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        Self::from_ron(&std::fs::read_to_string(path)?)
+    }
+}
+
+fn document_shortcuts(keys: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    // Shift is left for the other formats, see [`crate::scxml`]
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyS) {
+        commands.trigger(SaveDocument);
+    }
+    if keys.just_pressed(KeyCode::KeyO) {
+        commands.trigger(LoadDocument);
+    }
+}
+
+/// Everything the editor currently shows, for systems that need it as a [`Document`].
+#[derive(SystemParam)]
+pub struct CurrentDocument<'w, 's> {
+    state_types: Res<'w, StateTypes>,
+    nodes: Query<'w, 's, (Entity, &'static State, &'static CanvasPosition)>,
+    transitions: Query<'w, 's, &'static Transition>,
+}
+
+impl CurrentDocument<'_, '_> {
+    pub fn document(&self) -> Document {
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_key(|(entity, ..)| *entity);
+
+        let mut state_types = self
+            .state_types
+            .values()
+            .cloned()
+            .collect::<Vec<StateTypeData>>();
+        state_types.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Document {
+            version: DOCUMENT_VERSION,
+            state_types,
+            states: nodes
+                .iter()
+                .map(|(_, state, position)| DocumentState {
+                    id: state.id.clone(),
+                    name: state.name.clone(),
+                    position: position.0,
+                    state: state.state.clone(),
+                    parent: state.parent.clone(),
+                    parallel: state.parallel,
+                    history: state.history,
+                    initial: state.initial,
+                    terminal: state.terminal,
+                    on_entry: state.on_entry.clone(),
+                    on_exit: state.on_exit.clone(),
+                })
+                .collect(),
+            transitions: self.transitions.iter().cloned().collect(),
+        }
+    }
+}
+
+fn save_document(
+    _trigger: Trigger<SaveDocument>,
+    path: Res<DocumentPath>,
+    current: CurrentDocument,
+) {
+    match current.document().save(&path) {
+        Ok(()) => info!("Saved document to {}", path.display()),
+        Err(error) => warn!("Couldn't save document to {}: {error}", path.display()),
+    }
+}
+
+/// Keeps the current document open if the file can't be read.
+fn load_document(_trigger: Trigger<LoadDocument>, path: Res<DocumentPath>, mut commands: Commands) {
+    match Document::load(&path) {
+        Ok(document) => {
+            commands.queue(OpenDocument(document));
+            info!("Loaded document from {}", path.display());
+        }
+        Err(error) => warn!("Couldn't load document from {}: {error}", path.display()),
+    }
+}
+
+/// Replaces everything in the editor with a [`Document`], then triggers [`DocumentLoaded`].
+pub struct OpenDocument(pub Document);
+
+impl Command<Result> for OpenDocument {
+    fn apply(self, world: &mut World) -> Result {
+        let document = self.0;
+        let main_space = world
+            .query_filtered::<Entity, With<MainSpace>>()
+            .single(world)?;
+
+        let nodes = world
+            .query_filtered::<Entity, With<State>>()
+            .iter(world)
+            .collect::<Vec<_>>();
+        let transitions = world
+            .query_filtered::<Entity, With<Transition>>()
+            .iter(world)
+            .collect::<Vec<_>>();
+        for entity in nodes.into_iter().chain(transitions) {
+            world.despawn(entity);
+        }
+        let old_state_types = world
+            .resource_mut::<StateTypes>()
+            .drain()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for state_type in old_state_types {
+            world.trigger(StateTypeRemoved { state_type });
+        }
+
+        for state_type in document.state_types {
+            let state_type_id = state_type.id.clone();
+            world.resource_mut::<StateTypes>().insert(state_type);
+            world.trigger(StateTypeAdded {
+                state_type: state_type_id,
+            });
+        }
+
+        let mut commands = world.commands();
+        for state in document.states {
+            spawn_state_node(
+                &mut commands,
+                main_space,
+                state.position,
+                State {
+                    id: state.id,
+                    name: state.name,
+                    state: state.state,
+                    parent: state.parent,
+                    parallel: state.parallel,
+                    history: state.history,
+                    initial: state.initial,
+                    terminal: state.terminal,
+                    on_entry: state.on_entry,
+                    on_exit: state.on_exit,
+                },
+            );
+        }
+        for transition in document.transitions {
+            commands.spawn(transition);
+        }
+        world.flush();
+
+        world.trigger(DocumentLoaded);
+        Ok(())
+    }
+}
+
+/// Builders for the documents unit tests run against, with everything not named left empty.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+    use crate::guard::Guard;
+    use crate::{StateTypeLookup, StateTypeValue};
+
+    pub fn state(name: &str, parent: Option<&DocumentState>, initial: bool) -> DocumentState {
+        DocumentState {
+            id: rand::random(),
+            name: name.to_string(),
+            position: Vec2::ZERO,
+            state: vec![],
+            parent: parent.map(|parent| parent.id.clone()),
+            parallel: false,
+            history: None,
+            initial,
+            terminal: false,
+            on_entry: vec![],
+            on_exit: vec![],
+        }
+    }
+
+    /// A top-level state that assigns `value` to `variable`.
+    pub fn assigning(name: &str, variable: &StateTypeData, value: StateTypeValue) -> DocumentState {
+        let mut state = state(name, None, false);
+        state.state.push(StateValue {
+            state: variable.id.clone(),
+            value,
+            any: false,
+        });
+        state
+    }
+
+    pub fn transition(from: &DocumentState, to: &DocumentState) -> Transition {
+        Transition {
+            from: from.id.clone(),
+            to: to.id.clone(),
+            guard: None,
+            after: None,
+            actions: vec![],
+        }
+    }
+
+    /// A transition taken when `guard`, parsed against `state_types`, holds.
+    pub fn guarded(
+        from: &DocumentState,
+        to: &DocumentState,
+        guard: &str,
+        state_types: &impl StateTypeLookup,
+    ) -> Transition {
+        Transition {
+            guard: Some(Guard::parse(guard, state_types).unwrap()),
+            ..transition(from, to)
+        }
+    }
+
+    pub fn document(
+        state_types: Vec<StateTypeData>,
+        states: &[&DocumentState],
+        transitions: Vec<Transition>,
+    ) -> Document {
+        Document {
+            version: DOCUMENT_VERSION,
+            state_types,
+            states: states.iter().map(|&state| state.clone()).collect(),
+            transitions,
+        }
+    }
+}
@@ -0,0 +1,1969 @@
+use bevy::color::palettes::css;
+use bevy::ecs::system::SystemParam;
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+
+use crate::action::Action;
+use crate::bindings::{BindingsPlugin, InputBinding, no_bound_key_pressed};
+use crate::canvas::{Canvas, CanvasPlugin, CanvasPosition, pan_canvas, ui_position};
+use crate::codegen::CodegenPlugin;
+use crate::coverage::CoveragePlugin;
+use crate::document::DocumentPlugin;
+use crate::export::ExportPlugin;
+use crate::hierarchy::{HierarchyPlugin, Placement};
+use crate::history::{Edit, History, HistoryPlugin, Perform};
+use crate::scxml::ScxmlPlugin;
+use crate::simulation::{Simulation, SimulationPlugin};
+use crate::text_input::{
+    TextInput, TextInputActive, TextInputFocused, TextInputPlugin, TextInputUnfocused,
+    no_text_input_active,
+};
+use crate::validation::ValidationPlugin;
+use crate::{
+    HistoryKind, State, StateId, StateNodeId, StateType, StateTypeData, StateTypeValue, StateTypes,
+    StateValue, Transition,
+};
+
+/// The whole node editor: side panel, canvas, noodles and document saving/loading.
+///
+/// Doesn't spawn a camera, so the host app decides where the editor is rendered.
+pub struct StateMachineEditorPlugin;
+
+impl Plugin for StateMachineEditorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TextInputPlugin>() {
+            app.add_plugins(TextInputPlugin);
+        }
+        app.add_plugins((
+            BindingsPlugin,
+            CanvasPlugin,
+            CodegenPlugin,
+            CoveragePlugin,
+            DocumentPlugin,
+            ExportPlugin,
+            HierarchyPlugin,
+            HistoryPlugin,
+            ScxmlPlugin,
+            SimulationPlugin,
+            ValidationPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (
+                update_nodes,
+                draw_noodle,
+                drop_orphaned_noodles,
+                draw_initial_arrow,
+                position_guard_labels.after(update_nodes),
+                update_guard_labels,
+                update_action_lists,
+                delete_selected_nodes.run_if(no_text_input_active.and(no_bound_key_pressed)),
+                mark_selected_nodes.run_if(no_text_input_active.and(no_bound_key_pressed)),
+            ),
+        )
+        .add_observer(create_state_node)
+        .add_observer(add_state_type)
+        .add_observer(remove_state_type)
+        .add_observer(highlight_selected_node)
+        .add_observer(unhighlight_deselected_node)
+        .add_observer(add_guard_label)
+        .add_observer(remove_guard_label)
+        .add_observer(add_connector_observers)
+        .add_observer(add_node_observers)
+        .add_observer(add_state_to_side_panel)
+        .add_observer(remove_state_from_side_panel)
+        .add_observer(update_side_panel_state_name)
+        .add_observer(update_side_panel_state_type)
+        .add_observer(update_node_state_name);
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let root = commands
+        .spawn((Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            ..default()
+        },))
+        .id();
+
+    let side_panel = commands
+        .spawn((
+            Node {
+                width: Val::Px(200.0),
+                height: Val::Percent(100.0),
+                border: UiRect::all(Val::Px(5.0)).with_left(Val::Auto),
+                padding: UiRect::all(Val::Px(10.0)),
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            BackgroundColor(css::DARK_GRAY.into()),
+            BorderColor(css::GRAY.into()),
+            BorderRadius::right(Val::Px(10.0)),
+            FocusPolicy::Block,
+            ChildOf(root),
+        ))
+        .id();
+
+    let _side_panel_text = commands
+        .spawn((
+            SidePanel,
+            Node {
+                width: Val::Percent(100.0),
+                flex_grow: 1.0,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ChildOf(side_panel),
+        ))
+        .id();
+
+    let add_state_type_buttons = commands
+        .spawn((
+            Node {
+                flex_wrap: FlexWrap::Wrap,
+                column_gap: Val::Px(5.0),
+                row_gap: Val::Px(5.0),
+                ..default()
+            },
+            ChildOf(side_panel),
+        ))
+        .id();
+    for state_type in StateType::kinds() {
+        commands
+            .spawn((
+                Node {
+                    border: UiRect::all(Val::Px(3.0)),
+                    padding: UiRect::horizontal(Val::Px(5.0)),
+                    ..default()
+                },
+                BackgroundColor(css::GRAY.into()),
+                BorderColor(css::BLACK.into()),
+                Button,
+                ChildOf(add_state_type_buttons),
+                children![(
+                    Text(format!("+ {state_type}")),
+                    TextFont::from_font_size(12.0),
+                )],
+                AddStateTypeButton(state_type),
+            ))
+            .observe(click_add_state_type);
+    }
+
+    let main_space = commands
+        .spawn((
+            MainSpace,
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                overflow: Overflow::clip(),
+                ..default()
+            },
+            ChildOf(root),
+        ))
+        .observe(click_main_space)
+        .observe(pan_canvas)
+        .id();
+
+    let mut state_type_1 = StateTypeData::new("Move Input Held", StateType::Bool);
+    state_type_1.binding = Some(InputBinding::Key(KeyCode::ArrowRight));
+    let state_type_1_id = state_type_1.id.clone();
+    let mut state_type_2 = StateTypeData::new("Jump Input Held", StateType::Bool);
+    state_type_2.binding = Some(InputBinding::Key(KeyCode::Space));
+    let state_type_2_id = state_type_2.id.clone();
+
+    let mut state_types = StateTypes::default();
+    state_types.insert(state_type_1);
+    state_types.insert(state_type_2);
+    commands.insert_resource(state_types);
+
+    commands.trigger(StateTypeAdded {
+        state_type: state_type_1_id.clone(),
+    });
+    commands.trigger(StateTypeAdded {
+        state_type: state_type_2_id.clone(),
+    });
+
+    // Whether the character is on the ground is decided by the outer states, whether it moves by
+    // the ones inside them
+    for (name, position, jump, children) in [
+        (
+            "Grounded",
+            Vec2::new(50.0, 50.0),
+            false,
+            [
+                ("Idle", Vec2::new(50.0, 50.0)),
+                ("Walking", Vec2::new(500.0, 50.0)),
+            ],
+        ),
+        (
+            "Airborne",
+            Vec2::new(500.0, 250.0),
+            true,
+            [
+                ("Jumping", Vec2::new(50.0, 50.0)),
+                ("Hovering", Vec2::new(500.0, 50.0)),
+            ],
+        ),
+    ] {
+        let parent: StateNodeId = rand::random();
+        spawn_state_node(
+            &mut commands,
+            main_space,
+            position,
+            State {
+                id: parent.clone(),
+                name: name.to_string(),
+                state: vec![StateValue::new(
+                    state_type_2_id.clone(),
+                    StateTypeValue::Bool(jump),
+                )],
+                parent: None,
+                parallel: false,
+                history: None,
+                initial: name == "Grounded",
+                terminal: false,
+                on_entry: vec![],
+                on_exit: vec![],
+            },
+        );
+        for (index, (name, position)) in children.into_iter().enumerate() {
+            spawn_state_node(
+                &mut commands,
+                main_space,
+                position,
+                State {
+                    id: rand::random(),
+                    name: name.to_string(),
+                    state: vec![StateValue::new(
+                        state_type_1_id.clone(),
+                        StateTypeValue::Bool(index == 1),
+                    )],
+                    parent: Some(parent.clone()),
+                    parallel: false,
+                    history: None,
+                    initial: index == 0,
+                    terminal: false,
+                    on_entry: vec![],
+                    on_exit: vec![],
+                },
+            );
+        }
+    }
+}
+
+pub fn spawn_state_node(
+    commands: &mut Commands,
+    main_space: Entity,
+    position: Vec2,
+    state: State,
+) -> Entity {
+    commands
+        .spawn((
+            // Offsets come from the canvas position, border and padding from `update_nodes`
+            Node {
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            CanvasPosition(position),
+            BackgroundColor(css::MAROON.into()),
+            BorderColor(css::RED.into()),
+            BorderRadius::all(Val::Px(10.0)),
+            state,
+            Button,
+            ChildOf(main_space),
+        ))
+        .id()
+}
+
+#[derive(Component)]
+pub struct SidePanel;
+
+#[derive(Component)]
+pub struct MainSpace;
+
+/// Groups everything the side panel shows for one state type.
+#[derive(Component)]
+pub struct SidePanelEntry(pub StateId);
+
+#[derive(Component)]
+pub struct StateNameTextInput(pub StateId);
+
+#[derive(Component)]
+pub struct StateTypeParamsTextInput(pub StateId);
+
+/// Edits the value a [`State`] node assigns to a state type.
+#[derive(Component)]
+pub struct StateValueTextInput {
+    pub node: Entity,
+    pub state_type: StateId,
+}
+
+/// Flips the value a [`State`] node assigns to a bool or trigger when clicked, and whether the
+/// value is don't care when right clicked.
+#[derive(Component)]
+pub struct StateValueToggle {
+    pub node: Entity,
+    pub state_type: StateId,
+}
+
+#[derive(Component)]
+pub struct StateNameNodeText(pub StateId);
+
+/// Edits the entry actions of a [`State`] node, or its exit actions if `exit` is set.
+#[derive(Component)]
+pub struct ActionsTextInput {
+    pub node: Entity,
+    pub exit: bool,
+}
+
+#[derive(Event)]
+pub struct StateTypeAdded {
+    pub state_type: StateId,
+}
+
+#[derive(Event)]
+pub struct StateTypeNameChanged {
+    pub state_type: StateId,
+    pub name: String,
+}
+
+#[derive(Event)]
+pub struct StateTypeRemoved {
+    pub state_type: StateId,
+}
+
+/// The parameters of a state type changed, e.g. the range of a float or its input binding.
+#[derive(Event)]
+pub struct StateTypeChanged {
+    pub state_type: StateId,
+}
+
+/// Registers a new state type and gives every [`State`] node its default value.
+#[derive(Event, Debug)]
+pub struct AddStateType {
+    pub state_type: StateType,
+}
+
+/// Unregisters a state type and strips it from every [`State`] node.
+#[derive(Event, Debug)]
+pub struct RemoveStateType {
+    pub state_type: StateId,
+}
+
+#[derive(Component)]
+pub struct AddStateTypeButton(pub StateType);
+
+#[derive(Component)]
+pub struct RemoveStateTypeButton(pub StateId);
+
+fn click_add_state_type(
+    trigger: Trigger<Pointer<Click>>,
+    buttons: Query<&AddStateTypeButton>,
+    mut commands: Commands,
+) -> Result {
+    commands.trigger(AddStateType {
+        state_type: buttons.get(trigger.target())?.0.clone(),
+    });
+    Ok(())
+}
+
+fn click_remove_state_type(
+    trigger: Trigger<Pointer<Click>>,
+    buttons: Query<&RemoveStateTypeButton>,
+    mut commands: Commands,
+) -> Result {
+    commands.trigger(RemoveStateType {
+        state_type: buttons.get(trigger.target())?.0.clone(),
+    });
+    Ok(())
+}
+
+fn add_state_type(
+    trigger: Trigger<AddStateType>,
+    state_types: Res<StateTypes>,
+    states: Query<&State>,
+    mut commands: Commands,
+) {
+    let name = (1..)
+        .map(|n| match n {
+            1 => "New Variable".to_string(),
+            n => format!("New Variable {n}"),
+        })
+        .find(|name| state_types.values().all(|data| data.name != *name))
+        .unwrap();
+    let data = StateTypeData::new(name, trigger.state_type.clone());
+    debug!("Adding state type {:?}", data);
+    let values = states
+        .iter()
+        .map(|state| {
+            let value = StateValue::new(data.id.clone(), data.state_type.default_value());
+            (state.id.clone(), value)
+        })
+        .collect();
+    commands.queue(Perform(Edit::AddStateType { data, values }));
+}
+
+fn remove_state_type(
+    trigger: Trigger<RemoveStateType>,
+    state_types: Res<StateTypes>,
+    states: Query<&State>,
+    mut commands: Commands,
+) -> Result {
+    let data = state_types
+        .get(&trigger.state_type)
+        .ok_or("StateType not found")?
+        .clone();
+    debug!("Removing state type {:?}", data);
+    let values = states
+        .iter()
+        .filter_map(|state| {
+            let value = state.state.iter().find(|value| value.state == data.id)?;
+            Some((state.id.clone(), value.clone()))
+        })
+        .collect();
+    commands.queue(Perform(Edit::RemoveStateType { data, values }));
+    Ok(())
+}
+
+fn add_state_to_side_panel(
+    trigger: Trigger<StateTypeAdded>,
+    mut side_panel: Query<Entity, With<SidePanel>>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) -> Result {
+    let state_type = state_types
+        .get(&trigger.state_type)
+        .ok_or("StateType not found")?;
+    debug!("Adding state type to side panel: {:?}", state_type);
+    for panel in side_panel.iter_mut() {
+        let entry = commands
+            .spawn((
+                Node {
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::bottom(Val::Px(10.0)),
+                    ..default()
+                },
+                SidePanelEntry(state_type.id.clone()),
+                ChildOf(panel),
+            ))
+            .id();
+        let header = commands
+            .spawn((
+                Node {
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ChildOf(entry),
+            ))
+            .id();
+        commands
+            .spawn((
+                Node {
+                    flex_grow: 1.0,
+                    border: UiRect::all(Val::Px(5.0)),
+                    padding: UiRect::all(Val::Px(5.0)),
+                    ..default()
+                },
+                TextInput(state_type.name.clone()),
+                BackgroundColor(css::GRAY.into()),
+                BorderColor(css::BLACK.into()),
+                StateNameTextInput(state_type.id.clone()),
+                ChildOf(header),
+            ))
+            .observe(update_state_names)
+            .observe(text_field_focused_colors)
+            .observe(text_field_unfocused_colors);
+        commands
+            .spawn((
+                Node {
+                    margin: UiRect::left(Val::Px(5.0)),
+                    padding: UiRect::horizontal(Val::Px(5.0)),
+                    ..default()
+                },
+                BackgroundColor(css::MAROON.into()),
+                BorderRadius::all(Val::Px(3.0)),
+                Button,
+                RemoveStateTypeButton(state_type.id.clone()),
+                ChildOf(header),
+                children![Text("x".to_string())],
+            ))
+            .observe(click_remove_state_type);
+        commands.spawn((
+            Text(state_type.state_type.to_string()),
+            TextFont::from_font_size(12.0),
+            ChildOf(entry),
+        ));
+        if let Some(params) = state_type.state_type.params() {
+            commands
+                .spawn((
+                    Node {
+                        border: UiRect::all(Val::Px(5.0)),
+                        padding: UiRect::all(Val::Px(5.0)),
+                        ..default()
+                    },
+                    TextInput(params),
+                    BackgroundColor(css::GRAY.into()),
+                    BorderColor(css::BLACK.into()),
+                    StateTypeParamsTextInput(state_type.id.clone()),
+                    ChildOf(entry),
+                ))
+                .observe(update_state_type_params)
+                .observe(text_field_focused_colors)
+                .observe(text_field_unfocused_colors);
+        }
+    }
+    Ok(())
+}
+
+fn remove_state_from_side_panel(
+    trigger: Trigger<StateTypeRemoved>,
+    mut commands: Commands,
+    side_panel_entries: Query<(Entity, &SidePanelEntry)>,
+) {
+    for (entity, _) in side_panel_entries
+        .iter()
+        .filter(|(_, side_panel_entry)| side_panel_entry.0 == trigger.state_type)
+    {
+        commands.entity(entity).despawn();
+    }
+}
+
+fn update_side_panel_state_name(
+    trigger: Trigger<StateTypeNameChanged>,
+    mut state_name_text_inputs: Query<(&StateNameTextInput, &mut TextInput)>,
+) {
+    for (_, mut text_input) in state_name_text_inputs
+        .iter_mut()
+        .filter(|(state_name_text_input, _)| state_name_text_input.0 == trigger.state_type)
+    {
+        text_input.0 = trigger.name.clone();
+    }
+}
+
+fn update_node_state_name(
+    trigger: Trigger<StateTypeNameChanged>,
+    mut state_name_text_inputs: Query<(&StateNameNodeText, &mut Text)>,
+) {
+    for (_, mut text) in state_name_text_inputs
+        .iter_mut()
+        .filter(|(state_name_text_input, _)| state_name_text_input.0 == trigger.state_type)
+    {
+        text.0 = trigger.name.clone();
+    }
+}
+
+fn update_state_names(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<(&StateNameTextInput, &mut TextInput)>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) -> Result {
+    let (state_name, mut text_input) = text_inputs.get_mut(trigger.target())?;
+    let state_type = state_types
+        .get(&state_name.0)
+        .ok_or("StateType not found")?;
+    if text_input.0.is_empty() {
+        text_input.0 = state_type.name.clone();
+    } else if text_input.0 != state_type.name {
+        commands.queue(Perform(Edit::RenameStateType {
+            state_type: state_name.0.clone(),
+            old: state_type.name.clone(),
+            new: text_input.0.clone(),
+        }));
+    }
+    Ok(())
+}
+
+fn update_state_type_params(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<(&StateTypeParamsTextInput, &mut TextInput)>,
+    state_types: Res<StateTypes>,
+    states: Query<&State>,
+    mut commands: Commands,
+) -> Result {
+    let (params, mut text_input) = text_inputs.get_mut(trigger.target())?;
+    let state_type = state_types.get(&params.0).ok_or("StateType not found")?;
+    let Some(new_type) = state_type.state_type.with_params(&text_input.0) else {
+        text_input.0 = state_type.state_type.params().unwrap_or_default();
+        return Ok(());
+    };
+    text_input.0 = new_type.params().unwrap_or_default();
+    if new_type == state_type.state_type {
+        return Ok(());
+    }
+    debug!("Changing state type {} to {:?}", state_type.name, new_type);
+
+    let mut edits = vec![];
+    for state in states.iter() {
+        let Some(value) = state.state.iter().find(|value| value.state == params.0) else {
+            continue;
+        };
+        let coerced = new_type.coerce(value.value.clone());
+        if coerced != value.value {
+            edits.push(Edit::SetValue {
+                node: state.id.clone(),
+                state_type: params.0.clone(),
+                old: value.value.clone(),
+                new: coerced,
+            });
+        }
+    }
+    edits.push(Edit::SetStateType {
+        state_type: params.0.clone(),
+        old: state_type.state_type.clone(),
+        new: new_type,
+    });
+    commands.queue(Perform(Edit::Batch(edits)));
+    Ok(())
+}
+
+fn update_side_panel_state_type(
+    trigger: Trigger<StateTypeChanged>,
+    mut params_text_inputs: Query<(&StateTypeParamsTextInput, &mut TextInput)>,
+    state_types: Res<StateTypes>,
+) -> Result {
+    let state_type = state_types
+        .get(&trigger.state_type)
+        .ok_or("StateType not found")?;
+    for (_, mut text_input) in params_text_inputs
+        .iter_mut()
+        .filter(|(params, _)| params.0 == trigger.state_type)
+    {
+        text_input.0 = state_type.state_type.params().unwrap_or_default();
+    }
+    Ok(())
+}
+
+fn update_state_value(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<(&StateValueTextInput, &mut TextInput)>,
+    state_types: Res<StateTypes>,
+    states: Query<&State>,
+    mut commands: Commands,
+) -> Result {
+    let (value_input, mut text_input) = text_inputs.get_mut(trigger.target())?;
+    let state_type = state_types
+        .get(&value_input.state_type)
+        .ok_or("StateType not found")?;
+    let state = states.get(value_input.node)?;
+    let old = &state
+        .state
+        .iter()
+        .find(|value| value.state == value_input.state_type)
+        .ok_or("StateValue not found")?
+        .value;
+    match state_type.state_type.parse_value(&text_input.0) {
+        Some(new) if new != *old => {
+            commands.queue(Perform(Edit::SetValue {
+                node: state.id.clone(),
+                state_type: value_input.state_type.clone(),
+                old: old.clone(),
+                new,
+            }));
+        }
+        _ => text_input.0 = old.to_string(),
+    }
+    Ok(())
+}
+
+fn toggle_state_value(
+    trigger: Trigger<Pointer<Click>>,
+    toggles: Query<&StateValueToggle>,
+    states: Query<&State>,
+    mut commands: Commands,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let toggle = toggles.get(trigger.target())?;
+    let state = states.get(toggle.node)?;
+    let value = state
+        .state
+        .iter()
+        .find(|value| value.state == toggle.state_type)
+        .ok_or("StateValue not found")?;
+    let old = &value.value;
+    let new = match old {
+        _ if value.any => return Ok(()),
+        StateTypeValue::Bool(value) => StateTypeValue::Bool(!value),
+        StateTypeValue::Trigger(value) => StateTypeValue::Trigger(!value),
+        _ => return Ok(()),
+    };
+    commands.queue(Perform(Edit::SetValue {
+        node: state.id.clone(),
+        state_type: toggle.state_type.clone(),
+        old: old.clone(),
+        new,
+    }));
+    Ok(())
+}
+
+fn toggle_dont_care(
+    trigger: Trigger<Pointer<Click>>,
+    toggles: Query<&StateValueToggle>,
+    states: Query<&State>,
+    mut commands: Commands,
+) -> Result {
+    if trigger.button != PointerButton::Secondary {
+        return Ok(());
+    }
+    let toggle = toggles.get(trigger.target())?;
+    let state = states.get(toggle.node)?;
+    let value = state
+        .state
+        .iter()
+        .find(|value| value.state == toggle.state_type)
+        .ok_or("StateValue not found")?;
+    commands.queue(Perform(Edit::SetAny {
+        node: state.id.clone(),
+        state_type: toggle.state_type.clone(),
+        any: !value.any,
+    }));
+    Ok(())
+}
+
+fn text_field_focused_colors(
+    trigger: Trigger<TextInputFocused>,
+    mut text_inputs: Query<&mut BorderColor>,
+) -> Result {
+    let mut border_color = text_inputs.get_mut(trigger.target())?;
+    border_color.0 = css::WHITE.into();
+    Ok(())
+}
+
+fn text_field_unfocused_colors(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<&mut BorderColor>,
+) -> Result {
+    let mut border_color = text_inputs.get_mut(trigger.target())?;
+    border_color.0 = css::BLACK.into();
+    Ok(())
+}
+
+/// Font size of the text inside nodes, before zooming.
+const NODE_FONT_SIZE: f32 = 20.0;
+
+fn update_nodes(
+    mut nodes: Query<(Entity, Ref<State>, &mut Node)>,
+    mut commands: Commands,
+    state_types: Res<StateTypes>,
+    canvas: Res<Canvas>,
+    mut rendered_zoom: Local<f32>,
+    mut rendered_children: Local<HashMap<StateNodeId, Vec<String>>>,
+) {
+    // Everything inside a node is laid out at the current zoom, so zooming re-renders them all
+    let zoomed = *rendered_zoom != canvas.zoom;
+    *rendered_zoom = canvas.zoom;
+    let font = TextFont::from_font_size(canvas.scale(NODE_FONT_SIZE));
+    let small_font = TextFont::from_font_size(canvas.scale(NODE_FONT_SIZE * 0.7));
+
+    // Collapsed states list the states inside them, so they re-render when those change too
+    let mut children = HashMap::<StateNodeId, Vec<String>>::new();
+    for (_, state, _) in nodes.iter() {
+        if let Some(parent) = &state.parent
+            && state.history.is_none()
+        {
+            children
+                .entry(parent.clone())
+                .or_default()
+                .push(state.name.clone());
+        }
+    }
+    for names in children.values_mut() {
+        names.sort();
+    }
+
+    for (node, state, mut node_style) in nodes.iter_mut() {
+        let inside = children.get(&state.id);
+        if !zoomed && !state.is_changed() && rendered_children.get(&state.id) == inside {
+            continue;
+        }
+        match inside {
+            Some(inside) => rendered_children.insert(state.id.clone(), inside.clone()),
+            None => rendered_children.remove(&state.id),
+        };
+        node_style.border = UiRect::all(canvas.px(10.0));
+        node_style.padding = UiRect::all(canvas.px(10.0));
+        commands.entity(node).despawn_related::<Children>();
+        // Terminal states get a second border, like final states in most diagrams
+        if state.terminal {
+            commands.entity(node).insert(Outline::new(
+                canvas.px(3.0),
+                canvas.px(3.0),
+                css::WHITE.into(),
+            ));
+        } else {
+            commands.entity(node).remove::<Outline>();
+        }
+
+        // History pseudo-states are drawn as the usual circled H, and can only be entered
+        if let Some(kind) = state.history {
+            commands
+                .entity(node)
+                .insert(BorderRadius::all(Val::Percent(50.0)));
+            node_style.align_items = AlignItems::Center;
+            let label = match kind {
+                HistoryKind::Shallow => "H",
+                HistoryKind::Deep => "H*",
+            };
+            commands.spawn((Text(label.to_string()), font.clone(), ChildOf(node)));
+            commands.spawn((
+                Text::default(),
+                small_font.clone(),
+                TextColor(css::LIGHT_GRAY.into()),
+                ResumedText(state.id.clone()),
+                ChildOf(node),
+            ));
+        } else {
+            commands
+                .entity(node)
+                .insert(BorderRadius::all(Val::Px(10.0)));
+            node_style.align_items = AlignItems::default();
+            commands.spawn((Text(state.name.clone()), font.clone(), ChildOf(node)));
+        }
+        if let Some(inside) = inside {
+            // Regions of a parallel state are all active at once, alternatives only one at a time
+            let separator = if state.parallel { " | " } else { ", " };
+            commands.spawn((
+                Text(inside.join(separator)),
+                small_font.clone(),
+                TextColor(css::LIGHT_GRAY.into()),
+                ChildOf(node),
+            ));
+        }
+
+        for (connector, side) in [
+            (
+                Connector::Enter(state.id.clone()),
+                UiRect::left(canvas.px(-20.0)),
+            ),
+            (
+                Connector::Exit(state.id.clone()),
+                UiRect::right(canvas.px(-20.0)),
+            ),
+        ]
+        .into_iter()
+        .filter(|(connector, _)| {
+            state.history.is_none() || matches!(connector, Connector::Enter(_))
+        }) {
+            commands.spawn((
+                Node {
+                    width: canvas.px(15.0),
+                    height: canvas.px(15.0),
+                    border: UiRect::all(canvas.px(3.0)),
+                    position_type: PositionType::Absolute,
+                    left: side.left,
+                    right: side.right,
+                    ..default()
+                },
+                BackgroundColor(css::WHITE.into()),
+                BorderRadius::all(Val::Percent(100.0)),
+                BorderColor(css::BLACK.into()),
+                connector,
+                Button,
+                ChildOf(node),
+            ));
+        }
+        if state.history.is_some() {
+            continue;
+        }
+
+        for (state_name, state_value) in state.state.iter().filter_map(|value| {
+            let Some(state_type) = state_types.get(&value.state) else {
+                warn!("{} has a value for a missing state type", state.name);
+                return None;
+            };
+            Some((state_type.name.clone(), value))
+        }) {
+            let color = if state_value.any {
+                DONT_CARE_COLOR.into()
+            } else {
+                value_color(&state_value.value)
+            };
+            let toggleable = matches!(
+                state_value.value,
+                StateTypeValue::Bool(_) | StateTypeValue::Trigger(_)
+            );
+
+            let row = commands
+                .spawn((
+                    Node {
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ChildOf(node),
+                ))
+                .id();
+            commands.spawn((
+                Text(state_name),
+                font.clone(),
+                Node::default(),
+                TextColor(color),
+                StateNameNodeText(state_value.state.clone()),
+                ChildOf(row),
+            ));
+            commands.spawn((
+                Node {
+                    flex_grow: 1.0,
+                    ..default()
+                },
+                ChildOf(row),
+            ));
+            if state_value.any {
+                commands.spawn((
+                    Text("any".to_string()),
+                    font.clone(),
+                    TextColor(color),
+                    Node {
+                        margin: UiRect::left(canvas.px(5.0)),
+                        ..default()
+                    },
+                    ChildOf(row),
+                ));
+            } else if !toggleable {
+                commands
+                    .spawn((
+                        Node {
+                            min_width: canvas.px(40.0),
+                            margin: UiRect::left(canvas.px(5.0)),
+                            border: UiRect::all(canvas.px(3.0)),
+                            padding: UiRect::horizontal(canvas.px(3.0)),
+                            ..default()
+                        },
+                        TextInput(state_value.value.to_string()),
+                        font.clone(),
+                        BackgroundColor(css::GRAY.into()),
+                        BorderColor(css::BLACK.into()),
+                        StateValueTextInput {
+                            node,
+                            state_type: state_value.state.clone(),
+                        },
+                        ChildOf(row),
+                    ))
+                    .observe(update_state_value)
+                    .observe(text_field_focused_colors)
+                    .observe(text_field_unfocused_colors);
+            }
+            let dot_holder = commands
+                .spawn((
+                    Node {
+                        height: Val::Percent(50.0),
+                        ..default()
+                    },
+                    ChildOf(row),
+                ))
+                .id();
+            // Left click flips bools and triggers, right click toggles don't care
+            commands
+                .spawn((
+                    Node {
+                        width: canvas.px(15.0),
+                        height: canvas.px(15.0),
+                        border: UiRect::all(canvas.px(3.0)),
+                        position_type: PositionType::Absolute,
+                        left: canvas.px(15.0),
+                        ..default()
+                    },
+                    BackgroundColor(color),
+                    BorderRadius::all(Val::Percent(100.0)),
+                    BorderColor(css::BLACK.into()),
+                    StateValueToggle {
+                        node,
+                        state_type: state_value.state.clone(),
+                    },
+                    Button,
+                    ChildOf(dot_holder),
+                ))
+                .observe(toggle_state_value)
+                .observe(toggle_dont_care);
+        }
+
+        // Filled in by `update_action_lists`, which keeps up with renamed variables
+        for (label, exit) in [("entry /", false), ("exit /", true)] {
+            let row = commands
+                .spawn((
+                    Node {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::top(canvas.px(5.0)),
+                        ..default()
+                    },
+                    ChildOf(node),
+                ))
+                .id();
+            commands.spawn((
+                Text(label.to_string()),
+                small_font.clone(),
+                TextColor(css::LIGHT_GRAY.into()),
+                ChildOf(row),
+            ));
+            commands
+                .spawn((
+                    Node {
+                        flex_grow: 1.0,
+                        min_width: canvas.px(40.0),
+                        margin: UiRect::left(canvas.px(5.0)),
+                        border: UiRect::all(canvas.px(3.0)),
+                        padding: UiRect::horizontal(canvas.px(3.0)),
+                        ..default()
+                    },
+                    TextInput(String::new()),
+                    small_font.clone(),
+                    BackgroundColor(css::GRAY.into()),
+                    BorderColor(css::BLACK.into()),
+                    ActionsTextInput { node, exit },
+                    ChildOf(row),
+                ))
+                .observe(update_state_actions)
+                .observe(text_field_focused_colors)
+                .observe(text_field_unfocused_colors);
+        }
+    }
+}
+
+fn update_action_lists(
+    mut text_inputs: Query<(
+        &ActionsTextInput,
+        &mut TextInput,
+        &TextInputActive,
+        &mut BackgroundColor,
+    )>,
+    states: Query<&State>,
+    state_types: Res<StateTypes>,
+) {
+    for (actions_input, mut text_input, active, mut background) in text_inputs.iter_mut() {
+        if *active == TextInputActive::Active
+            || !(state_types.is_changed() || text_input.is_added())
+        {
+            continue;
+        }
+        let Ok(state) = states.get(actions_input.node) else {
+            continue;
+        };
+        let actions = if actions_input.exit {
+            &state.on_exit
+        } else {
+            &state.on_entry
+        };
+        let text = Action::display_list(actions, &*state_types);
+        if text_input.0 != text {
+            text_input.0 = text;
+        }
+        let problems = actions
+            .iter()
+            .any(|action| !action.problems(&*state_types).is_empty());
+        background.0 = if problems {
+            css::DARK_RED.into()
+        } else {
+            css::GRAY.into()
+        };
+    }
+}
+
+fn update_state_actions(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<(&ActionsTextInput, &mut TextInput)>,
+    states: Query<&State>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) -> Result {
+    let (actions_input, mut text_input) = text_inputs.get_mut(trigger.target())?;
+    let state = states.get(actions_input.node)?;
+    let old = if actions_input.exit {
+        &state.on_exit
+    } else {
+        &state.on_entry
+    };
+    let new = match Action::parse_list(&text_input.0, &*state_types) {
+        Ok(new) => new,
+        Err(error) => {
+            warn!("Invalid actions {:?}: {}", text_input.0, error);
+            text_input.0 = Action::display_list(old, &*state_types);
+            return Ok(());
+        }
+    };
+    if new == *old {
+        return Ok(());
+    }
+    let (node, old) = (state.id.clone(), old.clone());
+    commands.queue(Perform(if actions_input.exit {
+        Edit::SetExitActions { node, old, new }
+    } else {
+        Edit::SetEntryActions { node, old, new }
+    }));
+    Ok(())
+}
+
+/// Under a history pseudo-state, the states a transition to it would resume while playing, see
+/// [`crate::runtime::Runtime::resumed`].
+#[derive(Component)]
+pub struct ResumedText(pub StateNodeId);
+
+/// Values that don't matter for matching a state, see [`StateValue::any`].
+const DONT_CARE_COLOR: Srgba = css::MEDIUM_PURPLE;
+
+pub fn value_color(value: &StateTypeValue) -> Color {
+    match value {
+        StateTypeValue::Bool(true) => css::GREEN.into(),
+        StateTypeValue::Bool(false) | StateTypeValue::Trigger(false) => css::RED.into(),
+        StateTypeValue::Trigger(true) => css::GOLD.into(),
+        StateTypeValue::Int(_) | StateTypeValue::Float(_) | StateTypeValue::Enum(_) => {
+            css::LIGHT_SKY_BLUE.into()
+        }
+    }
+}
+
+/// A noodle that's currently being dragged out of a connector.
+#[derive(Component)]
+enum Noodle {
+    HangingStart {
+        start_position: Vec2,
+        end_connector: Entity,
+    },
+    HangingEnd {
+        start_connector: Entity,
+        end_position: Vec2,
+    },
+}
+
+impl Noodle {
+    /// The connector the noodle is being dragged out of.
+    fn connector(&self) -> Entity {
+        match self {
+            Noodle::HangingStart { end_connector, .. } => *end_connector,
+            Noodle::HangingEnd {
+                start_connector, ..
+            } => *start_connector,
+        }
+    }
+}
+
+#[derive(Component, Clone, Debug, PartialEq, Eq)]
+pub enum Connector {
+    Enter(StateNodeId),
+    Exit(StateNodeId),
+}
+
+/// Where noodles end on screen.
+#[derive(SystemParam)]
+struct NoodleEnds<'w, 's> {
+    connectors: Query<
+        'w,
+        's,
+        (
+            &'static Connector,
+            &'static GlobalTransform,
+            &'static ComputedNode,
+            &'static InheritedVisibility,
+        ),
+    >,
+    states: Query<'w, 's, &'static State>,
+}
+
+impl NoodleEnds<'_, '_> {
+    fn connector(&self, connector: Entity) -> Result<Vec2> {
+        let (_, transform, computed, _) = self.connectors.get(connector)?;
+        Ok(ui_position(transform, computed))
+    }
+
+    /// Where a noodle to or from `id` ends, and the state it ends at: its own connector while
+    /// it's shown, otherwise that of the closest state around it that is, so transitions into or
+    /// out of a collapsed state end at it.
+    fn shown_connector(
+        &self,
+        id: &StateNodeId,
+        connector: fn(StateNodeId) -> Connector,
+    ) -> Option<(StateNodeId, Vec2)> {
+        let mut id = id.clone();
+        // Bounded in case a state ends up nested in itself
+        for _ in 0..=self.states.iter().count() {
+            let wanted = connector(id.clone());
+            if let Some((_, transform, computed, _)) = self
+                .connectors
+                .iter()
+                .find(|(connector, .., visibility)| **connector == wanted && visibility.get())
+            {
+                return Some((id, ui_position(transform, computed)));
+            }
+            id = self
+                .states
+                .iter()
+                .find(|state| state.id == id)?
+                .parent
+                .clone()?;
+        }
+        None
+    }
+
+    /// Both ends of the noodle of `transition`, if it's shown at all.
+    fn transition(&self, transition: &Transition) -> Option<(Vec2, Vec2)> {
+        let (from, start) = self.shown_connector(&transition.from, Connector::Exit)?;
+        let (to, end) = self.shown_connector(&transition.to, Connector::Enter)?;
+        // Transitions between states inside the same collapsed state would loop back into it
+        if from == to && transition.from != transition.to {
+            return None;
+        }
+        Some((start, end))
+    }
+}
+
+/// Ends the drag of a noodle whose connector went away, e.g. because zooming re-rendered the nodes,
+/// since the connector's [`Pointer<DragEnd>`] won't come anymore.
+fn drop_orphaned_noodles(
+    noodles: Query<(Entity, &Noodle)>,
+    connectors: Query<(), With<Connector>>,
+    mut commands: Commands,
+) {
+    for (entity, noodle) in noodles.iter() {
+        if !connectors.contains(noodle.connector()) {
+            debug!("Connector went away mid drag, dropping noodle");
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn draw_noodle(
+    noodles: Query<&Noodle>,
+    transitions: Query<&Transition>,
+    ends: NoodleEnds,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    canvas: Res<Canvas>,
+    simulation: Option<Res<Simulation>>,
+    mut gizmos: Gizmos,
+) -> Result {
+    let (camera, camera_transform) = camera.single()?;
+    let to_world = |position: Vec2| camera.viewport_to_world_2d(camera_transform, position);
+    let white = Color::srgb(1.0, 1.0, 1.0);
+    let flash = simulation
+        .as_ref()
+        .and_then(|simulation| simulation.flash());
+    let mut curves = vec![];
+    for noodle in noodles.iter() {
+        // The connector can be gone mid drag, see [`drop_orphaned_noodles`]
+        let Ok(connector) = ends.connector(noodle.connector()) else {
+            continue;
+        };
+        curves.push(match noodle {
+            Noodle::HangingStart { start_position, .. } => {
+                (*start_position, connector, white, None)
+            }
+            Noodle::HangingEnd { end_position, .. } => (connector, *end_position, white, None),
+        });
+    }
+    for transition in transitions.iter() {
+        // Either node may be mid re-render, in which case its connectors are back next frame
+        let Some((start, end)) = ends.transition(transition) else {
+            continue;
+        };
+        // The transitions the simulation just took fade from gold back to white
+        let color = match flash {
+            Some((flashed, left))
+                if flashed.iter().any(|flashed| {
+                    flashed.from == transition.from && flashed.to == transition.to
+                }) =>
+            {
+                white.mix(&css::GOLD.into(), left)
+            }
+            _ => white,
+        };
+        // How long the machine has waited for a timed transition out of a state it's in
+        let progress = simulation
+            .as_ref()
+            .and_then(|simulation| simulation.runtime.progress(transition));
+        curves.push((start, end, color, progress));
+    }
+
+    let handle = Vec2::new(canvas.scale(100.0), 0.0);
+    for (start, end, color, progress) in curves {
+        let bezier = CubicBezier::new([[
+            to_world(start)?,
+            to_world(start + handle)?,
+            to_world(end - handle)?,
+            to_world(end)?,
+        ]]);
+        let curve = bezier.to_curve().unwrap();
+        let resolution = 100 * curve.segments().len();
+        gizmos.linestrip(
+            curve.iter_positions(resolution).map(|pt| pt.extend(0.0)),
+            color,
+        );
+        if let Some(progress) = progress {
+            gizmos.linestrip_2d(
+                (0..=resolution).map(|i| curve.position(progress * i as f32 / resolution as f32)),
+                css::GOLD,
+            );
+            gizmos.circle_2d(curve.position(progress), canvas.scale(5.0), css::GOLD);
+        }
+    }
+    Ok(())
+}
+
+/// Points at the initial state shown on the canvas, or at the one in each lane of a parallel
+/// state.
+fn draw_initial_arrow(
+    nodes: Query<(
+        &State,
+        &InheritedVisibility,
+        &GlobalTransform,
+        &ComputedNode,
+    )>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    canvas: Res<Canvas>,
+    mut gizmos: Gizmos,
+) -> Result {
+    let (camera, camera_transform) = camera.single()?;
+    for (_, _, transform, computed) in nodes
+        .iter()
+        .filter(|(state, visibility, ..)| state.initial && visibility.get())
+    {
+        let corner = ui_position(transform, computed)
+            - computed.size() * computed.inverse_scale_factor() / 2.0;
+        let start = corner - Vec2::splat(canvas.scale(40.0));
+        gizmos.arrow_2d(
+            camera.viewport_to_world_2d(camera_transform, start)?,
+            camera.viewport_to_world_2d(camera_transform, corner)?,
+            Color::srgb(1.0, 1.0, 1.0),
+        );
+    }
+    Ok(())
+}
+
+/// Shows and edits the guard of the [`Transition`] entity it points at, halfway along its noodle.
+#[derive(Component)]
+pub struct GuardTextInput(pub Entity);
+
+fn add_guard_label(
+    trigger: Trigger<OnAdd, Transition>,
+    main_space: Query<Entity, With<MainSpace>>,
+    mut commands: Commands,
+) -> Result {
+    // Sized by `position_guard_labels`, which keeps up with the zoom
+    commands
+        .spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            TextInput(String::new()),
+            TextFont::from_font_size(NODE_FONT_SIZE),
+            BackgroundColor(css::GRAY.into()),
+            BorderColor(css::BLACK.into()),
+            Visibility::Hidden,
+            GuardTextInput(trigger.target()),
+            ChildOf(main_space.single()?),
+        ))
+        .observe(update_transition_guard)
+        .observe(text_field_focused_colors)
+        .observe(text_field_unfocused_colors);
+    Ok(())
+}
+
+fn remove_guard_label(
+    trigger: Trigger<OnRemove, Transition>,
+    labels: Query<(Entity, &GuardTextInput)>,
+    mut commands: Commands,
+) {
+    for (label, _) in labels
+        .iter()
+        .filter(|(_, label)| label.0 == trigger.target())
+    {
+        commands.entity(label).despawn();
+    }
+}
+
+fn position_guard_labels(
+    mut labels: Query<(
+        &GuardTextInput,
+        &mut Node,
+        &mut TextFont,
+        &mut Visibility,
+        &ComputedNode,
+    )>,
+    transitions: Query<&Transition>,
+    ends: NoodleEnds,
+    canvas: Res<Canvas>,
+) {
+    let font_size = canvas.scale(NODE_FONT_SIZE);
+
+    for (label, mut node, mut font, mut visibility, computed) in labels.iter_mut() {
+        // The label is despawned along with its transition, but not until commands are applied
+        let Ok(transition) = transitions.get(label.0) else {
+            continue;
+        };
+        let Some((start, end)) = ends.transition(transition) else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        // The noodle is symmetric, so its midpoint is the midpoint of its ends
+        let position = (start + end) / 2.0
+            - canvas.viewport.min
+            - computed.size() * computed.inverse_scale_factor() / 2.0;
+        node.left = Val::Px(position.x);
+        node.top = Val::Px(position.y);
+        node.min_width = canvas.px(40.0);
+        node.border = UiRect::all(canvas.px(3.0));
+        node.padding = UiRect::horizontal(canvas.px(3.0));
+        if font.font_size != font_size {
+            font.font_size = font_size;
+        }
+        *visibility = Visibility::Inherited;
+    }
+}
+
+fn update_guard_labels(
+    mut labels: Query<(
+        &GuardTextInput,
+        &mut TextInput,
+        &TextInputActive,
+        &mut BackgroundColor,
+    )>,
+    transitions: Query<Ref<Transition>>,
+    state_types: Res<StateTypes>,
+) {
+    for (label, mut text_input, active, mut background) in labels.iter_mut() {
+        let Ok(transition) = transitions.get(label.0) else {
+            continue;
+        };
+        if *active == TextInputActive::Active
+            || !(state_types.is_changed() || transition.is_changed() || text_input.is_added())
+        {
+            continue;
+        }
+        let text = transition.label(&*state_types);
+        let mut problems = match &transition.guard {
+            Some(guard) => guard.problems(&*state_types),
+            None => vec![],
+        };
+        for action in transition.actions.iter() {
+            problems.extend(action.problems(&*state_types));
+        }
+        if text_input.0 != text {
+            text_input.0 = text;
+        }
+        background.0 = if problems.is_empty() {
+            css::GRAY.into()
+        } else {
+            css::DARK_RED.into()
+        };
+    }
+}
+
+fn update_transition_guard(
+    trigger: Trigger<TextInputUnfocused>,
+    mut labels: Query<(&GuardTextInput, &mut TextInput)>,
+    transitions: Query<&Transition>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) -> Result {
+    let (label, mut text_input) = labels.get_mut(trigger.target())?;
+    let transition = transitions.get(label.0)?;
+    let new = match transition.with_label(&text_input.0, &*state_types) {
+        Ok(new) => new,
+        Err(error) => {
+            warn!("Invalid transition label {:?}: {}", text_input.0, error);
+            text_input.0 = transition.label(&*state_types);
+            return Ok(());
+        }
+    };
+    let mut edits = vec![];
+    if new.after != transition.after {
+        edits.push(Edit::SetDelay {
+            from: transition.from.clone(),
+            to: transition.to.clone(),
+            old: transition.after,
+            new: new.after,
+        });
+    }
+    if new.guard != transition.guard {
+        edits.push(Edit::SetGuard {
+            from: transition.from.clone(),
+            to: transition.to.clone(),
+            old: transition.guard.clone(),
+            new: new.guard,
+        });
+    }
+    if new.actions != transition.actions {
+        edits.push(Edit::SetTransitionActions {
+            from: transition.from.clone(),
+            to: transition.to.clone(),
+            old: transition.actions.clone(),
+            new: new.actions,
+        });
+    }
+    if !edits.is_empty() {
+        commands.queue(Perform(Edit::Batch(edits)));
+    }
+    Ok(())
+}
+
+#[derive(Component)]
+struct DraggedConnector {
+    noodle: Entity,
+}
+
+fn add_node_observers(trigger: Trigger<OnAdd, State>, mut commands: Commands) {
+    commands
+        .entity(trigger.target())
+        .observe(start_dragging_node)
+        .observe(be_dragging_node)
+        .observe(stop_dragging_node)
+        .observe(select_node);
+}
+
+fn add_connector_observers(trigger: Trigger<OnAdd, Connector>, mut commands: Commands) {
+    commands
+        .entity(trigger.target())
+        .observe(start_dragging_connector)
+        .observe(be_dragging_connector)
+        .observe(drag_and_drop_connector)
+        .observe(stop_dragging_connector);
+}
+
+fn start_dragging_connector(
+    trigger: Trigger<Pointer<DragStart>>,
+    connectors: Query<&Connector>,
+    mut commands: Commands,
+    window: Query<&Window>,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let window = window.single()?;
+    let connector = trigger.target();
+    let noodle = match connectors.get(connector)? {
+        Connector::Enter(_) => commands
+            .spawn((Noodle::HangingStart {
+                start_position: window.cursor_position().unwrap_or_default(),
+                end_connector: connector,
+            },))
+            .id(),
+        Connector::Exit(_) => commands
+            .spawn((Noodle::HangingEnd {
+                start_connector: connector,
+                end_position: window.cursor_position().unwrap_or_default(),
+            },))
+            .id(),
+    };
+    commands
+        .entity(connector)
+        .insert(DraggedConnector { noodle });
+    Ok(())
+}
+
+fn be_dragging_connector(
+    trigger: Trigger<Pointer<Drag>>,
+    dragged_connectors: Query<&DraggedConnector>,
+    mut noodles: Query<&mut Noodle>,
+    window: Query<&Window>,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let connector = trigger.target();
+    let noodle = dragged_connectors.get(connector)?.noodle;
+    let mut noodle = noodles.get_mut(noodle)?;
+    let window = window.single()?;
+    *noodle = match *noodle {
+        Noodle::HangingStart { start_position, .. } => Noodle::HangingStart {
+            start_position: window.cursor_position().unwrap_or(start_position),
+            end_connector: connector,
+        },
+        Noodle::HangingEnd { end_position, .. } => Noodle::HangingEnd {
+            start_connector: connector,
+            end_position: window.cursor_position().unwrap_or(end_position),
+        },
+    };
+    Ok(())
+}
+
+fn drag_and_drop_connector(
+    trigger: Trigger<Pointer<DragDrop>>,
+    mut commands: Commands,
+    connectors: Query<&Connector>,
+    transitions: Query<&Transition>,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let transition = match (
+        connectors.get(trigger.dropped)?,
+        connectors.get(trigger.target())?,
+    ) {
+        (Connector::Exit(from), Connector::Enter(to))
+        | (Connector::Enter(to), Connector::Exit(from)) => Transition {
+            from: from.clone(),
+            to: to.clone(),
+            guard: None,
+            after: None,
+            actions: vec![],
+        },
+        _ => {
+            debug!("Noodle connected to wrong side, removing");
+            return Ok(());
+        }
+    };
+
+    if transitions
+        .iter()
+        .any(|existing| existing.from == transition.from && existing.to == transition.to)
+    {
+        debug!("Noodle already exists, removing");
+        return Ok(());
+    }
+
+    // The hanging noodle itself is cleaned up by [`stop_dragging_connector`]
+    debug!("Connecting noodle");
+    commands.queue(Perform(Edit::AddTransition(transition)));
+
+    Ok(())
+}
+
+fn stop_dragging_connector(
+    trigger: Trigger<Pointer<DragEnd>>,
+    mut commands: Commands,
+    dragged_connectors: Query<&DraggedConnector>,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let connector = trigger.target();
+    let noodle = match dragged_connectors.get(connector) {
+        Ok(dragged) => dragged.noodle,
+        Err(_) => return Ok(()),
+    };
+    debug!("Dropping noodle");
+    commands.entity(noodle).despawn();
+    commands.entity(connector).remove::<DraggedConnector>();
+
+    Ok(())
+}
+
+/// Where a node was when the current drag started, so the whole drag is a single [`Edit`].
+#[derive(Component)]
+struct DraggedNode {
+    start: Vec2,
+}
+
+fn start_dragging_node(
+    trigger: Trigger<Pointer<DragStart>>,
+    nodes: Query<&CanvasPosition, With<State>>,
+    mut commands: Commands,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let position = nodes.get(trigger.target())?;
+    commands
+        .entity(trigger.target())
+        .insert(DraggedNode { start: position.0 });
+    Ok(())
+}
+
+fn stop_dragging_node(
+    trigger: Trigger<Pointer<DragEnd>>,
+    nodes: Query<(&State, &CanvasPosition, &DraggedNode)>,
+    mut history: ResMut<History>,
+    mut commands: Commands,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let (state, position, dragged) = nodes.get(trigger.target())?;
+    let end = position.0;
+    if end != dragged.start {
+        history.record(Edit::MoveNode {
+            node: state.id.clone(),
+            old: dragged.start,
+            new: end,
+        });
+    }
+    commands.entity(trigger.target()).remove::<DraggedNode>();
+    Ok(())
+}
+
+fn be_dragging_node(
+    trigger: Trigger<Pointer<Drag>>,
+    mut nodes: Query<&mut CanvasPosition>,
+    children: Query<&Children>,
+    interactions: Query<&Interaction>,
+    canvas: Res<Canvas>,
+) -> Result {
+    if trigger.button != PointerButton::Primary {
+        return Ok(());
+    }
+    let node = trigger.target();
+    if children.iter_descendants(node).any(|child| {
+        interactions
+            .get(child)
+            .is_ok_and(|i| !matches!(i, Interaction::None))
+    }) {
+        return Ok(());
+    }
+
+    nodes.get_mut(node)?.0 += trigger.delta / canvas.zoom;
+    Ok(())
+}
+
+/// Marks a [`State`] node as selected, e.g. for deletion.
+#[derive(Component)]
+pub struct Selected;
+
+const DOUBLE_CLICK_SECONDS: f64 = 0.4;
+
+fn click_main_space(
+    trigger: Trigger<Pointer<Click>>,
+    time: Res<Time>,
+    mut last_click: Local<Option<f64>>,
+    canvas: Res<Canvas>,
+    selected: Query<Entity, With<Selected>>,
+    mut commands: Commands,
+) {
+    // Clicks on nodes bubble up to here too
+    if trigger.event().target != trigger.target() || trigger.button != PointerButton::Primary {
+        return;
+    }
+    for node in selected.iter() {
+        commands.entity(node).remove::<Selected>();
+    }
+
+    let now = time.elapsed_secs_f64();
+    let double_click = last_click.is_some_and(|last| now - last < DOUBLE_CLICK_SECONDS);
+    *last_click = (!double_click).then_some(now);
+    if !double_click {
+        return;
+    }
+
+    commands.trigger(CreateStateNode {
+        position: canvas.from_window(trigger.pointer_location.position),
+    });
+}
+
+/// Spawns a new [`State`] node with a default value for every state type, in the state the
+/// canvas shows. In a parallel state that's the region whose lane it's created in, or a new
+/// region when it's created outside of all lanes.
+#[derive(Event, Debug)]
+pub struct CreateStateNode {
+    pub position: Vec2,
+}
+
+fn create_state_node(
+    trigger: Trigger<CreateStateNode>,
+    side_panel: Query<&Children, With<SidePanel>>,
+    side_panel_entries: Query<&SidePanelEntry>,
+    states: Query<&State>,
+    state_types: Res<StateTypes>,
+    placement: Placement,
+    mut commands: Commands,
+) {
+    let (parent, position) = placement.place(trigger.position);
+    // Follow the side panel's order so new nodes list their variables the same way
+    let state = side_panel
+        .iter()
+        .flat_map(|entries| entries.iter())
+        .filter_map(|entry| side_panel_entries.get(entry).ok())
+        .filter_map(|entry| state_types.get(&entry.0))
+        .map(|state_type| {
+            StateValue::new(state_type.id.clone(), state_type.state_type.default_value())
+        })
+        .collect();
+    let name = (1..)
+        .map(|n| match n {
+            1 => "New State".to_string(),
+            n => format!("New State {n}"),
+        })
+        .find(|name| states.iter().all(|state| state.name != *name))
+        .unwrap();
+    debug!("Creating state {} at {}", name, trigger.position);
+
+    commands.queue(Perform(Edit::AddNode {
+        state: State {
+            id: rand::random(),
+            name,
+            state,
+            // A machine needs to start somewhere, so the first state does unless told otherwise,
+            // and so does the first state in a parent
+            initial: states
+                .iter()
+                .all(|state| state.parent != parent || !state.initial),
+            parent,
+            parallel: false,
+            history: None,
+            terminal: false,
+            on_entry: vec![],
+            on_exit: vec![],
+        },
+        position,
+    }));
+}
+
+fn select_node(
+    trigger: Trigger<Pointer<Click>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    selected: Query<Entity, With<Selected>>,
+    mut commands: Commands,
+) {
+    let node = trigger.target();
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        if selected.contains(node) {
+            commands.entity(node).remove::<Selected>();
+        } else {
+            commands.entity(node).insert(Selected);
+        }
+        return;
+    }
+    for other in selected.iter().filter(|other| *other != node) {
+        commands.entity(other).remove::<Selected>();
+    }
+    commands.entity(node).insert(Selected);
+}
+
+fn highlight_selected_node(
+    trigger: Trigger<OnAdd, Selected>,
+    mut nodes: Query<&mut BorderColor>,
+) -> Result {
+    nodes.get_mut(trigger.target())?.0 = css::WHITE.into();
+    Ok(())
+}
+
+fn unhighlight_deselected_node(
+    trigger: Trigger<OnRemove, Selected>,
+    mut nodes: Query<&mut BorderColor>,
+) {
+    // The node may be getting despawned
+    if let Ok(mut border_color) = nodes.get_mut(trigger.target()) {
+        border_color.0 = css::RED.into();
+    }
+}
+
+/// Deletes the selected nodes along with everything nested in them.
+fn delete_selected_nodes(
+    keys: Res<ButtonInput<KeyCode>>,
+    states: Query<(&State, &CanvasPosition, Has<Selected>)>,
+    transitions: Query<&Transition>,
+    mut commands: Commands,
+) {
+    if !keys.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        return;
+    }
+    let mut deleted = states
+        .iter()
+        .filter(|(.., selected)| *selected)
+        .map(|(state, position, _)| (state, position.0))
+        .collect::<Vec<_>>();
+    if deleted.is_empty() {
+        return;
+    }
+    let mut index = 0;
+    while let Some((parent, _)) = deleted.get(index) {
+        let parent = parent.id.clone();
+        for (state, position, _) in states.iter() {
+            if state.parent.as_ref() == Some(&parent)
+                && !deleted.iter().any(|(other, _)| other.id == state.id)
+            {
+                deleted.push((state, position.0));
+            }
+        }
+        index += 1;
+    }
+    let is_deleted = |id: &StateNodeId| deleted.iter().any(|(state, _)| state.id == *id);
+    let mut edits = transitions
+        .iter()
+        .filter(|transition| is_deleted(&transition.from) || is_deleted(&transition.to))
+        .map(|transition| Edit::RemoveTransition(transition.clone()))
+        .collect::<Vec<_>>();
+    for (state, position) in deleted.iter() {
+        debug!("Deleting state {}", state.name);
+        edits.push(Edit::RemoveNode {
+            state: (*state).clone(),
+            position: *position,
+        });
+    }
+    commands.queue(Perform(Edit::Batch(edits)));
+}
+
+/// `I` makes the selected node the initial state of its parent, or clears it if it already is, `F`
+/// toggles whether the selected nodes are terminal and `R` whether the states nested in them are
+/// parallel regions. `H` turns the selected nodes into shallow history pseudo-states, then deep
+/// ones, then back into states.
+fn mark_selected_nodes(
+    keys: Res<ButtonInput<KeyCode>>,
+    states: Query<(&State, Has<Selected>)>,
+    mut commands: Commands,
+) {
+    if keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::ShiftLeft,
+        KeyCode::ShiftRight,
+    ]) {
+        return;
+    }
+    let mut selected = states
+        .iter()
+        .filter(|(_, selected)| *selected)
+        .map(|(state, _)| state);
+    if keys.just_pressed(KeyCode::KeyI) {
+        // Only one state per parent can be initial, so this needs exactly one selected
+        if let (Some(state), None) = (selected.next(), selected.next()) {
+            let old = states
+                .iter()
+                .find(|(other, _)| other.initial && other.parent == state.parent)
+                .map(|(state, _)| state.id.clone());
+            let new = (!state.initial).then(|| state.id.clone());
+            commands.queue(Perform(Edit::SetInitial { old, new }));
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyF) {
+        let edits = states
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(state, _)| Edit::SetTerminal {
+                node: state.id.clone(),
+                terminal: !state.terminal,
+            })
+            .collect::<Vec<_>>();
+        if !edits.is_empty() {
+            commands.queue(Perform(Edit::Batch(edits)));
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyH) {
+        let edits = states
+            .iter()
+            .filter(|(_, selected)| *selected)
+            // A history pseudo-state stands in for its parent's children, so top-level and
+            // initial states can't be one, but can still have it cleared
+            .filter(|(state, _)| {
+                state.parent.is_some() && !state.initial || state.history.is_some()
+            })
+            .map(|(state, _)| Edit::SetHistory {
+                node: state.id.clone(),
+                old: state.history,
+                new: match state.history {
+                    _ if state.parent.is_none() || state.initial => None,
+                    None => Some(HistoryKind::Shallow),
+                    Some(HistoryKind::Shallow) => Some(HistoryKind::Deep),
+                    Some(HistoryKind::Deep) => None,
+                },
+            })
+            .collect::<Vec<_>>();
+        if !edits.is_empty() {
+            commands.queue(Perform(Edit::Batch(edits)));
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyR) {
+        let edits = states
+            .iter()
+            .filter(|(_, selected)| *selected)
+            .map(|(state, _)| Edit::SetParallel {
+                node: state.id.clone(),
+                parallel: !state.parallel,
+            })
+            .collect::<Vec<_>>();
+        if !edits.is_empty() {
+            commands.queue(Perform(Edit::Batch(edits)));
+        }
+    }
+}
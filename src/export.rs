@@ -0,0 +1,565 @@
+//! Text formats for looking at a machine outside the editor, e.g. in design docs.
+
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use crate::action::Action;
+use crate::bindings::no_bound_key_pressed;
+use crate::document::{CurrentDocument, Document, DocumentPath, DocumentState};
+use crate::text_input::no_text_input_active;
+use crate::{HistoryKind, StateNodeId, StateTypeLookup};
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            export_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+        )
+        .add_observer(export_dot)
+        .add_observer(export_mermaid)
+        .add_observer(export_plant_uml);
+    }
+}
+
+/// Writes a Graphviz digraph next to the [`DocumentPath`], with a `dot` extension.
+#[derive(Event, Debug, Default)]
+pub struct ExportDot;
+
+/// Writes a Mermaid `stateDiagram-v2` next to the [`DocumentPath`], with an `mmd` extension.
+#[derive(Event, Debug, Default)]
+pub struct ExportMermaid;
+
+/// Writes a PlantUML state diagram next to the [`DocumentPath`], with a `puml` extension.
+#[derive(Event, Debug, Default)]
+pub struct ExportPlantUml;
+
+fn export_shortcuts(keys: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyG) {
+        commands.trigger(ExportDot);
+    }
+    if keys.just_pressed(KeyCode::KeyM) {
+        commands.trigger(ExportMermaid);
+    }
+    if keys.just_pressed(KeyCode::KeyU) {
+        commands.trigger(ExportPlantUml);
+    }
+}
+
+/// Warns instead of failing if `contents` couldn't be generated or written.
+fn report_export(path: &DocumentPath, extension: &str, contents: Result<String>) {
+    let path = path.with_extension(extension);
+    let written = contents.and_then(|contents| Ok(std::fs::write(&path, contents)?));
+    match written {
+        Ok(()) => info!("Exported {}", path.display()),
+        Err(error) => warn!("Couldn't export {}: {error}", path.display()),
+    }
+}
+
+fn export_dot(_trigger: Trigger<ExportDot>, path: Res<DocumentPath>, current: CurrentDocument) {
+    report_export(&path, "dot", to_dot(&current.document()));
+}
+
+fn export_mermaid(
+    _trigger: Trigger<ExportMermaid>,
+    path: Res<DocumentPath>,
+    current: CurrentDocument,
+) {
+    report_export(&path, "mmd", to_mermaid(&current.document()));
+}
+
+fn export_plant_uml(
+    _trigger: Trigger<ExportPlantUml>,
+    path: Res<DocumentPath>,
+    current: CurrentDocument,
+) {
+    report_export(&path, "puml", to_plant_uml(&current.document()));
+}
+
+/// A short id for a state, as the formats here don't like arbitrary names as ids.
+fn state_key(document: &Document, id: &StateNodeId) -> Result<String> {
+    Ok(document
+        .states
+        .iter()
+        .position(|state| state.id == *id)
+        .map(|index| format!("s{index}"))
+        .ok_or("Transition refers to a missing state")?)
+}
+
+/// A digraph with a box per state, labelled with its name, values and actions, and an edge per
+/// transition, labelled with its delay, guard and actions. The initial state gets an entry arrow
+/// from a point and terminal states a double border. States with children become clusters, dashed
+/// for parallel ones, and edges to or from them attach to the cluster's border. History
+/// pseudo-states are circled `H`s.
+///
+/// Top level nodes are pinned where they are on the canvas, which `neato -n` respects, while
+/// `dot` itself lays them out from scratch.
+pub fn to_dot(document: &Document) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "digraph {{")?;
+    writeln!(out, "    compound=true;")?;
+    writeln!(out, "    node [shape=box, style=rounded];")?;
+    write_dot_states(&mut out, document, None, 1)?;
+    if let Some(initial) = document.initial_state() {
+        writeln!(out, "    start [shape=point];")?;
+        writeln!(
+            out,
+            "    start -> {};",
+            dot_end(document, &initial.id, "lhead")?
+        )?;
+    }
+    for transition in document.transitions.iter() {
+        let (from, tail) = dot_node(document, &transition.from)?;
+        let (to, head) = dot_node(document, &transition.to)?;
+        let mut attributes = vec![];
+        attributes.extend(tail.map(|cluster| format!("ltail={cluster}")));
+        attributes.extend(head.map(|cluster| format!("lhead={cluster}")));
+        let label = transition.label(&document.state_types);
+        if !label.is_empty() {
+            attributes.push(format!("label={}", dot_string(&label)));
+        }
+        write!(out, "    {from} -> {to}")?;
+        if !attributes.is_empty() {
+            write!(out, " [{}]", attributes.join(", "))?;
+        }
+        writeln!(out, ";")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(out)
+}
+
+/// The states nested directly in `parent`, as nodes and clusters.
+fn write_dot_states(
+    out: &mut String,
+    document: &Document,
+    parent: Option<&StateNodeId>,
+    depth: usize,
+) -> Result {
+    let indent = "    ".repeat(depth);
+    for state in document.children(parent) {
+        let key = state_key(document, &state.id)?;
+        let mut label = vec![state.name.clone()];
+        label.extend(state_values(document, state));
+        label.extend(state_actions(document, state));
+        let label = dot_string(&label.join("\n"));
+        if document.is_composite(&state.id) {
+            writeln!(out, "{indent}subgraph cluster_{key} {{")?;
+            writeln!(out, "{indent}    label={label};")?;
+            if state.terminal {
+                writeln!(out, "{indent}    peripheries=2;")?;
+            }
+            if state.parallel {
+                writeln!(out, "{indent}    style=dashed;")?;
+            }
+            write_dot_states(out, document, Some(&state.id), depth + 1)?;
+            writeln!(out, "{indent}}}")?;
+            continue;
+        }
+        write!(out, "{indent}{key} [label={label}")?;
+        // Nested states are placed relative to their own parent, which Graphviz can't express
+        if parent.is_none() {
+            write!(
+                out,
+                ", pos=\"{},{}!\"",
+                state.position.x,
+                // Graphviz's y axis points up, the canvas's down
+                -state.position.y
+            )?;
+        }
+        if state.terminal {
+            write!(out, ", peripheries=2")?;
+        }
+        writeln!(out, "];")?;
+    }
+    for history in document.history_states(parent) {
+        writeln!(
+            out,
+            "{indent}{} [label={}, shape=circle];",
+            state_key(document, &history.id)?,
+            dot_string(history_label(history))
+        )?;
+    }
+    Ok(())
+}
+
+/// The node an edge to or from `id` attaches to, and the cluster to clip it at if `id` has
+/// children.
+fn dot_node(document: &Document, id: &StateNodeId) -> Result<(String, Option<String>)> {
+    if !document.is_composite(id) {
+        return Ok((state_key(document, id)?, None));
+    }
+    let entered = document
+        .entry_paths(id)
+        .first()
+        .and_then(|path| path.last())
+        .map(|state| state.id.clone())
+        .ok_or("Transition refers to a missing state")?;
+    Ok((
+        state_key(document, &entered)?,
+        Some(format!("cluster_{}", state_key(document, id)?)),
+    ))
+}
+
+/// [`dot_node`] as an edge end on its own, with the cluster as `attribute`.
+fn dot_end(document: &Document, id: &StateNodeId, attribute: &str) -> Result<String> {
+    Ok(match dot_node(document, id)? {
+        (node, Some(cluster)) => format!("{node} [{attribute}={cluster}]"),
+        (node, None) => node,
+    })
+}
+
+/// A `stateDiagram-v2` with the initial and terminal states marked, entry and exit actions as
+/// state descriptions and transitions labelled with their delays, guards and actions. States with
+/// children become composite states, with the regions of parallel ones split by `--`. Mermaid has
+/// no history pseudo-states, so they're plain states named `H` or `H*`.
+pub fn to_mermaid(document: &Document) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "stateDiagram-v2")?;
+    write_mermaid_states(&mut out, document, None, 1)?;
+    for transition in document.transitions.iter() {
+        write!(
+            out,
+            "    {} --> {}",
+            state_key(document, &transition.from)?,
+            state_key(document, &transition.to)?
+        )?;
+        let label = transition.label(&document.state_types);
+        if !label.is_empty() {
+            write!(out, " : {}", mermaid_label(&label))?;
+        }
+        writeln!(out)?;
+    }
+    Ok(out)
+}
+
+/// Mermaid reads quotes and line breaks as syntax, even in labels.
+fn mermaid_label(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', " ")
+}
+
+/// The states nested directly in `parent`, with their own initial and terminal markers, or as
+/// regions if `parent` is parallel.
+fn write_mermaid_states(
+    out: &mut String,
+    document: &Document,
+    parent: Option<&StateNodeId>,
+    depth: usize,
+) -> Result {
+    let indent = "    ".repeat(depth);
+    let parallel = is_parallel(document, parent);
+    for (index, state) in document.children(parent).enumerate() {
+        if parallel && index > 0 {
+            writeln!(out, "{indent}--")?;
+        }
+        let key = state_key(document, &state.id)?;
+        writeln!(out, "{indent}{key} : {}", mermaid_label(&state.name))?;
+        for actions in state_actions(document, state) {
+            writeln!(out, "{indent}{key} : {}", mermaid_label(&actions))?;
+        }
+        if document.is_composite(&state.id) {
+            writeln!(out, "{indent}state {key} {{")?;
+            write_mermaid_states(out, document, Some(&state.id), depth + 1)?;
+            writeln!(out, "{indent}}}")?;
+        }
+        // Each region gets its own markers, before the `--` starting the next one
+        if parallel {
+            writeln!(out, "{indent}[*] --> {key}")?;
+            if state.terminal {
+                writeln!(out, "{indent}{key} --> [*]")?;
+            }
+        }
+    }
+    for history in document.history_states(parent) {
+        writeln!(
+            out,
+            "{indent}{} : {}",
+            state_key(document, &history.id)?,
+            history_label(history)
+        )?;
+    }
+    if parallel {
+        return Ok(());
+    }
+    if let Some(initial) = document.children(parent).find(|state| state.initial) {
+        writeln!(out, "{indent}[*] --> {}", state_key(document, &initial.id)?)?;
+    }
+    for state in document.children(parent).filter(|state| state.terminal) {
+        writeln!(out, "{indent}{} --> [*]", state_key(document, &state.id)?)?;
+    }
+    Ok(())
+}
+
+/// The PlantUML equivalent of [`to_mermaid`], using PlantUML's own `[H]` and `[H*]` for history
+/// pseudo-states.
+pub fn to_plant_uml(document: &Document) -> Result<String> {
+    let mut out = String::new();
+    writeln!(out, "@startuml")?;
+    write_plant_uml_states(&mut out, document, None, 0)?;
+    for transition in document.transitions.iter() {
+        write!(
+            out,
+            "{} --> {}",
+            plant_uml_key(document, &transition.from)?,
+            plant_uml_key(document, &transition.to)?
+        )?;
+        let label = transition.label(&document.state_types);
+        if !label.is_empty() {
+            write!(out, " : {}", plant_uml_label(&label))?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "@enduml")?;
+    Ok(out)
+}
+
+/// PlantUML has no escape for quotes inside a quoted name, and reads line breaks as the end of
+/// the line.
+fn plant_uml_label(text: &str) -> String {
+    text.replace('"', "'").replace('\n', " ")
+}
+
+/// [`state_key`], or the history of the parent for a history pseudo-state.
+fn plant_uml_key(document: &Document, id: &StateNodeId) -> Result<String> {
+    let state = document
+        .state(id)
+        .ok_or("Transition refers to a missing state")?;
+    if state.history.is_none() {
+        return state_key(document, id);
+    }
+    let parent = match &state.parent {
+        Some(parent) => state_key(document, parent)?,
+        None => String::new(),
+    };
+    Ok(format!("{parent}[{}]", history_label(state)))
+}
+
+/// The PlantUML equivalent of [`write_mermaid_states`].
+fn write_plant_uml_states(
+    out: &mut String,
+    document: &Document,
+    parent: Option<&StateNodeId>,
+    depth: usize,
+) -> Result {
+    let indent = "  ".repeat(depth);
+    let parallel = is_parallel(document, parent);
+    for (index, state) in document.children(parent).enumerate() {
+        if parallel && index > 0 {
+            writeln!(out, "{indent}--")?;
+        }
+        let key = state_key(document, &state.id)?;
+        write!(
+            out,
+            "{indent}state \"{}\" as {key}",
+            plant_uml_label(&state.name)
+        )?;
+        if document.is_composite(&state.id) {
+            writeln!(out, " {{")?;
+            write_plant_uml_states(out, document, Some(&state.id), depth + 1)?;
+            writeln!(out, "{indent}}}")?;
+        } else {
+            writeln!(out)?;
+        }
+        for actions in state_actions(document, state) {
+            writeln!(out, "{indent}{key} : {}", plant_uml_label(&actions))?;
+        }
+        // Each region gets its own markers, before the `--` starting the next one
+        if parallel {
+            writeln!(out, "{indent}[*] --> {key}")?;
+            if state.terminal {
+                writeln!(out, "{indent}{key} --> [*]")?;
+            }
+        }
+    }
+    if parallel {
+        return Ok(());
+    }
+    if let Some(initial) = document.children(parent).find(|state| state.initial) {
+        writeln!(out, "{indent}[*] --> {}", state_key(document, &initial.id)?)?;
+    }
+    for state in document.children(parent).filter(|state| state.terminal) {
+        writeln!(out, "{indent}{} --> [*]", state_key(document, &state.id)?)?;
+    }
+    Ok(())
+}
+
+fn history_label(state: &DocumentState) -> &'static str {
+    match state.history {
+        Some(HistoryKind::Deep) => "H*",
+        _ => "H",
+    }
+}
+
+fn is_parallel(document: &Document, id: Option<&StateNodeId>) -> bool {
+    id.and_then(|id| document.state(id))
+        .is_some_and(|state| state.parallel)
+}
+
+/// `name = value` for everything `state` assigns, skipping values of types that no longer exist.
+fn state_values(document: &Document, state: &DocumentState) -> Vec<String> {
+    state
+        .state
+        .iter()
+        .filter_map(|value| {
+            let data = document.state_types.state_type(&value.state)?;
+            Some(if value.any {
+                format!("{} = any", data.name)
+            } else {
+                format!("{} = {}", data.name, value.value)
+            })
+        })
+        .collect()
+}
+
+/// `entry / ...` and `exit / ...` for the actions `state` has.
+fn state_actions(document: &Document, state: &DocumentState) -> Vec<String> {
+    [("entry", &state.on_entry), ("exit", &state.on_exit)]
+        .into_iter()
+        .filter(|(_, actions)| !actions.is_empty())
+        .map(|(label, actions)| {
+            format!(
+                "{label} / {}",
+                Action::display_list(actions, &document.state_types)
+            )
+        })
+        .collect()
+}
+
+fn dot_string(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::fixtures::{document, state, transition};
+    use crate::guard::Guard;
+    use crate::{StateType, StateTypeData};
+
+    #[test]
+    fn dot_clusters_nested_states_and_pins_top_level_ones() {
+        let mut idle = state("Idle", None, true);
+        idle.position = Vec2::new(10.0, 20.0);
+        let mut air = state("Air", None, false);
+        air.position = Vec2::new(300.0, 40.0);
+        let rising = state("Rising", Some(&air), true);
+        let falling = state("Falling", Some(&air), false);
+        let document = document(
+            vec![],
+            &[&idle, &air, &rising, &falling],
+            vec![
+                transition(&idle, &air),
+                transition(&rising, &falling),
+                transition(&air, &idle),
+            ],
+        );
+        assert_eq!(
+            to_dot(&document).unwrap(),
+            r#"digraph {
+    compound=true;
+    node [shape=box, style=rounded];
+    s0 [label="Idle", pos="10,-20!"];
+    subgraph cluster_s1 {
+        label="Air";
+        s2 [label="Rising"];
+        s3 [label="Falling"];
+    }
+    start [shape=point];
+    start -> s0;
+    s0 -> s2 [lhead=cluster_s1];
+    s2 -> s3;
+    s2 -> s0 [ltail=cluster_s1];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn mermaid_escapes_labels_and_marks_each_region() {
+        let state_types = vec![StateTypeData::new("Is moving", StateType::Bool)];
+        let mut both = state("Both \"at once\"", None, true);
+        both.parallel = true;
+        let left = state("Left", Some(&both), false);
+        let mut right = state("Right", Some(&both), false);
+        right.terminal = true;
+        right.on_entry.push(Action::Emit("Done\nnow".into()));
+        let idle = state("Idle", Some(&left), true);
+        let moving = state("Moving", Some(&left), false);
+        let mut moves = transition(&idle, &moving);
+        moves.guard = Some(Guard::parse("\"Is moving\"", &state_types).unwrap());
+        let document = document(
+            state_types,
+            &[&both, &left, &right, &idle, &moving],
+            vec![moves],
+        );
+        assert_eq!(
+            to_mermaid(&document).unwrap(),
+            r#"stateDiagram-v2
+    s0 : Both #quot;at once#quot;
+    state s0 {
+        s1 : Left
+        state s1 {
+            s3 : Idle
+            s4 : Moving
+            [*] --> s3
+        }
+        [*] --> s1
+        --
+        s2 : Right
+        s2 : entry / emit #quot;Done now#quot;
+        [*] --> s2
+        s2 --> [*]
+    }
+    [*] --> s0
+    s3 --> s4 : #quot;Is moving#quot;
+"#
+        );
+    }
+
+    #[test]
+    fn plant_uml_escapes_labels_and_marks_each_region() {
+        let state_types = vec![StateTypeData::new("Is moving", StateType::Bool)];
+        let mut both = state("Both \"at once\"", None, true);
+        both.parallel = true;
+        let left = state("Left", Some(&both), false);
+        let mut right = state("Right", Some(&both), false);
+        right.terminal = true;
+        let idle = state("Idle", Some(&left), true);
+        let moving = state("Moving", Some(&left), false);
+        let mut moves = transition(&idle, &moving);
+        moves.guard = Some(Guard::parse("\"Is moving\"", &state_types).unwrap());
+        let document = document(
+            state_types,
+            &[&both, &left, &right, &idle, &moving],
+            vec![moves],
+        );
+        assert_eq!(
+            to_plant_uml(&document).unwrap(),
+            "@startuml
+state \"Both 'at once'\" as s0 {
+  state \"Left\" as s1 {
+    state \"Idle\" as s3
+    state \"Moving\" as s4
+    [*] --> s3
+  }
+  [*] --> s1
+  --
+  state \"Right\" as s2
+  [*] --> s2
+  s2 --> [*]
+}
+[*] --> s0
+s3 --> s4 : 'Is moving'
+@enduml
+"
+        );
+    }
+}
@@ -0,0 +1,792 @@
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{StateId, StateType, StateTypeData, StateTypeLookup, StateTypeValue};
+
+/// How many combinations of inputs [`Guard::can_hold`] tries before giving up.
+pub const MAX_COMBINATIONS: usize = 4096;
+
+/// A condition over the state variables that has to hold for a [`crate::Transition`] to be taken.
+///
+/// Variables are referenced by id, so renaming one only changes how the guard is displayed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Guard {
+    Literal(bool),
+    /// A bool or trigger variable on its own.
+    Variable(StateId),
+    Compare {
+        variable: StateId,
+        op: CompareOp,
+        value: StateTypeValue,
+    },
+    Not(Box<Guard>),
+    And(Box<Guard>, Box<Guard>),
+    Or(Box<Guard>, Box<Guard>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        }
+    }
+
+    pub fn is_ordering(&self) -> bool {
+        !matches!(self, CompareOp::Eq | CompareOp::Ne)
+    }
+}
+
+impl Guard {
+    pub fn parse(source: &str, state_types: &impl StateTypeLookup) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            state_types,
+        };
+        let guard = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(format!("Unexpected {token:?} in guard").into());
+        }
+        Ok(guard)
+    }
+
+    pub fn evaluate(&self, inputs: &HashMap<StateId, StateTypeValue>) -> bool {
+        match self {
+            Guard::Literal(value) => *value,
+            Guard::Variable(variable) => matches!(
+                inputs.get(variable),
+                Some(StateTypeValue::Bool(true) | StateTypeValue::Trigger(true))
+            ),
+            Guard::Compare {
+                variable,
+                op,
+                value,
+            } => inputs
+                .get(variable)
+                .is_some_and(|input| compare(input, *op, value)),
+            Guard::Not(guard) => !guard.evaluate(inputs),
+            Guard::And(a, b) => a.evaluate(inputs) && b.evaluate(inputs),
+            Guard::Or(a, b) => a.evaluate(inputs) || b.evaluate(inputs),
+        }
+    }
+
+    /// Every variable this guard reads.
+    pub fn variables(&self) -> Vec<&StateId> {
+        match self {
+            Guard::Literal(_) => vec![],
+            Guard::Variable(variable) | Guard::Compare { variable, .. } => vec![variable],
+            Guard::Not(guard) => guard.variables(),
+            Guard::And(a, b) | Guard::Or(a, b) => {
+                let mut variables = a.variables();
+                variables.extend(b.variables());
+                variables
+            }
+        }
+    }
+
+    /// Everything that stopped making sense since the guard was written, e.g. because a variable
+    /// it reads was removed or changed type.
+    pub fn problems(&self, state_types: &impl StateTypeLookup) -> Vec<String> {
+        match self {
+            Guard::Literal(_) => vec![],
+            Guard::Variable(variable) => match state_types.state_type(variable) {
+                None => vec!["Reads a state variable that no longer exists".to_string()],
+                Some(data) => check_truthy(data).err().into_iter().collect(),
+            },
+            Guard::Compare {
+                variable,
+                op,
+                value,
+            } => match state_types.state_type(variable) {
+                None => vec!["Reads a state variable that no longer exists".to_string()],
+                Some(data) => check_comparison(data, *op, value)
+                    .err()
+                    .into_iter()
+                    .collect(),
+            },
+            Guard::Not(guard) => guard.problems(state_types),
+            Guard::And(a, b) | Guard::Or(a, b) => {
+                let mut problems = a.problems(state_types);
+                problems.extend(b.problems(state_types));
+                problems
+            }
+        }
+    }
+
+    /// Whether any inputs make the guard true, with the variables in `fixed` held at their value.
+    ///
+    /// Only the values a comparison can tell apart are tried, which is exact, but past
+    /// [`MAX_COMBINATIONS`] of them this gives up and assumes the guard can hold.
+    pub fn can_hold(
+        &self,
+        state_types: &impl StateTypeLookup,
+        fixed: &HashMap<StateId, StateTypeValue>,
+    ) -> bool {
+        let mut variables: Vec<&StateId> = vec![];
+        for variable in self.variables() {
+            if !variables.contains(&variable) && !fixed.contains_key(variable) {
+                variables.push(variable);
+            }
+        }
+        // Variables without a type are missing from the inputs, like they are while running
+        let candidates = variables
+            .into_iter()
+            .filter_map(|variable| {
+                let data = state_types.state_type(variable)?;
+                Some((variable, self.candidates(variable, &data.state_type)))
+            })
+            .collect::<Vec<_>>();
+        let combinations = candidates
+            .iter()
+            .try_fold(1usize, |total, (_, values)| total.checked_mul(values.len()));
+        if combinations.is_none_or(|combinations| combinations > MAX_COMBINATIONS) {
+            return true;
+        }
+
+        let mut inputs = fixed.clone();
+        let mut indices = vec![0; candidates.len()];
+        loop {
+            for ((variable, values), index) in candidates.iter().zip(indices.iter()) {
+                inputs.insert((*variable).clone(), values[*index].clone());
+            }
+            if self.evaluate(&inputs) {
+                return true;
+            }
+            // Counts through every combination, like an odometer
+            let Some(position) = indices
+                .iter()
+                .zip(candidates.iter())
+                .position(|(index, (_, values))| index + 1 < values.len())
+            else {
+                return false;
+            };
+            indices[position] += 1;
+            indices[..position].fill(0);
+        }
+    }
+
+    /// Values of `variable` between which the outcome of every comparison with it can change.
+    fn candidates(&self, variable: &StateId, state_type: &StateType) -> Vec<StateTypeValue> {
+        let mut literals = vec![];
+        self.collect_literals(variable, &mut literals);
+        let mut values = match state_type {
+            StateType::Bool => vec![StateTypeValue::Bool(false), StateTypeValue::Bool(true)],
+            StateType::Trigger => vec![
+                StateTypeValue::Trigger(false),
+                StateTypeValue::Trigger(true),
+            ],
+            StateType::Enum(variants) => variants
+                .iter()
+                .map(|variant| StateTypeValue::Enum(variant.clone()))
+                .collect(),
+            StateType::Int => literals
+                .iter()
+                .flat_map(|literal| {
+                    let (floor, ceil) = (literal.floor() as i64, literal.ceil() as i64);
+                    // Casting saturates, so the neighbours of huge literals have to as well
+                    [floor.saturating_sub(1), floor, ceil, ceil.saturating_add(1)]
+                })
+                .map(StateTypeValue::Int)
+                .collect(),
+            StateType::Float { min, max } => {
+                let mut points = literals;
+                points.extend(min.iter().chain(max.iter()));
+                points.sort_by(f32::total_cmp);
+                points.dedup();
+                let mut values = points.clone();
+                values.extend(points.windows(2).map(|pair| (pair[0] + pair[1]) / 2.0));
+                values.extend(points.first().map(|first| first - 1.0));
+                values.extend(points.last().map(|last| last + 1.0));
+                values.into_iter().map(StateTypeValue::Float).collect()
+            }
+        };
+        values.retain(|value| state_type.accepts(value));
+        values.dedup();
+        if values.is_empty() {
+            values.push(state_type.default_value());
+        }
+        values
+    }
+
+    /// The numbers `variable` is compared to.
+    fn collect_literals(&self, variable: &StateId, literals: &mut Vec<f32>) {
+        match self {
+            Guard::Compare {
+                variable: compared,
+                value,
+                ..
+            } if compared == variable => match value {
+                StateTypeValue::Int(value) => literals.push(*value as f32),
+                StateTypeValue::Float(value) => literals.push(*value),
+                _ => {}
+            },
+            Guard::Literal(_) | Guard::Variable(_) | Guard::Compare { .. } => {}
+            Guard::Not(guard) => guard.collect_literals(variable, literals),
+            Guard::And(a, b) | Guard::Or(a, b) => {
+                a.collect_literals(variable, literals);
+                b.collect_literals(variable, literals);
+            }
+        }
+    }
+
+    /// Writes the guard back out in the syntax [`Guard::parse`] reads, using the current names.
+    pub fn display(&self, state_types: &impl StateTypeLookup) -> String {
+        self.display_with_precedence(state_types, 0)
+    }
+
+    fn display_with_precedence(&self, state_types: &impl StateTypeLookup, outer: u8) -> String {
+        let (precedence, text) = match self {
+            Guard::Literal(value) => (3, value.to_string()),
+            Guard::Variable(variable) => (3, variable_name(variable, state_types)),
+            Guard::Compare {
+                variable,
+                op,
+                value,
+            } => (
+                3,
+                format!(
+                    "{} {} {}",
+                    variable_name(variable, state_types),
+                    op.symbol(),
+                    display_value(value)
+                ),
+            ),
+            Guard::Not(guard) => (
+                2,
+                format!("not {}", guard.display_with_precedence(state_types, 2)),
+            ),
+            Guard::And(a, b) => (
+                1,
+                format!(
+                    "{} and {}",
+                    a.display_with_precedence(state_types, 1),
+                    b.display_with_precedence(state_types, 1)
+                ),
+            ),
+            Guard::Or(a, b) => (
+                0,
+                format!(
+                    "{} or {}",
+                    a.display_with_precedence(state_types, 0),
+                    b.display_with_precedence(state_types, 0)
+                ),
+            ),
+        };
+        if precedence < outer {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+}
+
+pub fn compare(input: &StateTypeValue, op: CompareOp, value: &StateTypeValue) -> bool {
+    // A fired trigger compares like a set bool
+    let as_bool = |value: &StateTypeValue| match value {
+        StateTypeValue::Trigger(fired) => StateTypeValue::Bool(*fired),
+        value => value.clone(),
+    };
+    let (input, value) = (&as_bool(input), &as_bool(value));
+    let ordering = match (input, value) {
+        (StateTypeValue::Int(a), StateTypeValue::Int(b)) => a.partial_cmp(b),
+        (StateTypeValue::Float(a), StateTypeValue::Float(b)) => a.partial_cmp(b),
+        (StateTypeValue::Int(a), StateTypeValue::Float(b)) => (*a as f32).partial_cmp(b),
+        (StateTypeValue::Float(a), StateTypeValue::Int(b)) => a.partial_cmp(&(*b as f32)),
+        (a, b) => match op {
+            CompareOp::Eq => return a == b,
+            CompareOp::Ne => return a != b,
+            _ => return false,
+        },
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match op {
+        CompareOp::Eq => ordering.is_eq(),
+        CompareOp::Ne => ordering.is_ne(),
+        CompareOp::Lt => ordering.is_lt(),
+        CompareOp::Le => ordering.is_le(),
+        CompareOp::Gt => ordering.is_gt(),
+        CompareOp::Ge => ordering.is_ge(),
+    }
+}
+
+fn check_truthy(data: &StateTypeData) -> Result<(), String> {
+    match data.state_type {
+        StateType::Bool | StateType::Trigger => Ok(()),
+        _ => Err(format!(
+            "{} is a {} and needs to be compared to a value",
+            data.name, data.state_type
+        )),
+    }
+}
+
+fn check_comparison(
+    data: &StateTypeData,
+    op: CompareOp,
+    value: &StateTypeValue,
+) -> Result<(), String> {
+    let numeric = matches!(data.state_type, StateType::Int | StateType::Float { .. });
+    if op.is_ordering() && !numeric {
+        return Err(format!(
+            "{} is a {} and can't be compared with {}",
+            data.name,
+            data.state_type,
+            op.symbol()
+        ));
+    }
+    let compatible = match (&data.state_type, value) {
+        (StateType::Int | StateType::Float { .. }, StateTypeValue::Int(_))
+        | (StateType::Int | StateType::Float { .. }, StateTypeValue::Float(_)) => true,
+        (StateType::Enum(variants), StateTypeValue::Enum(variant)) => {
+            if !variants.contains(variant) {
+                return Err(format!("{} has no variant {variant}", data.name));
+            }
+            true
+        }
+        (StateType::Bool, StateTypeValue::Bool(_))
+        | (StateType::Trigger, StateTypeValue::Bool(_) | StateTypeValue::Trigger(_)) => true,
+        _ => false,
+    };
+    if compatible {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is a {} and can't be compared to {}",
+            data.name,
+            data.state_type,
+            display_value(value)
+        ))
+    }
+}
+
+/// How a variable is written in guards and actions, quoted if it isn't a plain identifier.
+pub fn variable_name(variable: &StateId, state_types: &impl StateTypeLookup) -> String {
+    match state_types.state_type(variable) {
+        Some(data) => quote_if_needed(&data.name),
+        None => "<missing>".to_string(),
+    }
+}
+
+/// How a value is written in guards and actions.
+pub fn display_value(value: &StateTypeValue) -> String {
+    match value {
+        StateTypeValue::Enum(variant) => quote_if_needed(variant),
+        StateTypeValue::Float(value) if value.fract() == 0.0 => format!("{value:.1}"),
+        value => value.to_string(),
+    }
+}
+
+/// Quotes names that wouldn't be read back as a single identifier, escaping quotes and
+/// backslashes inside them.
+pub fn quote_if_needed(name: &str) -> String {
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name);
+    if is_identifier {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+const KEYWORDS: [&str; 5] = ["and", "or", "not", "true", "false"];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    Quoted(String),
+    Number(String),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => quoted.push(c),
+                            None => return Err("Unterminated quote in guard".into()),
+                        },
+                        Some(c) => quoted.push(c),
+                        None => return Err("Unterminated quote in guard".into()),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    return Err(format!("Expected {c}{c} in guard").into());
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '=' | '!' | '<' | '>' => {
+                let equals = chars.next_if_eq(&'=').is_some();
+                match (c, equals) {
+                    ('=', true) => Token::Op(CompareOp::Eq),
+                    ('!', true) => Token::Op(CompareOp::Ne),
+                    ('!', false) => Token::Not,
+                    ('<', false) => Token::Op(CompareOp::Lt),
+                    ('<', true) => Token::Op(CompareOp::Le),
+                    ('>', false) => Token::Op(CompareOp::Gt),
+                    ('>', true) => Token::Op(CompareOp::Ge),
+                    _ => return Err("Expected == in guard".into()),
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                Token::Number(number)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Identifier(word),
+                }
+            }
+            c => return Err(format!("Unexpected {c:?} in guard").into()),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a, L> {
+    tokens: Vec<Token>,
+    position: usize,
+    state_types: &'a L,
+}
+
+impl<L: StateTypeLookup> Parser<'_, L> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or("Unexpected end of guard")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn or(&mut self) -> Result<Guard> {
+        let mut guard = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            guard = Guard::Or(Box::new(guard), Box::new(self.and()?));
+        }
+        Ok(guard)
+    }
+
+    fn and(&mut self) -> Result<Guard> {
+        let mut guard = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.position += 1;
+            guard = Guard::And(Box::new(guard), Box::new(self.unary()?));
+        }
+        Ok(guard)
+    }
+
+    fn unary(&mut self) -> Result<Guard> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Guard::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Guard> {
+        let name = match self.next()? {
+            Token::Open => {
+                let guard = self.or()?;
+                if self.next()? != Token::Close {
+                    return Err("Expected ) in guard".into());
+                }
+                return Ok(guard);
+            }
+            Token::Identifier(word) if word == "true" => return Ok(Guard::Literal(true)),
+            Token::Identifier(word) if word == "false" => return Ok(Guard::Literal(false)),
+            Token::Identifier(name) | Token::Quoted(name) => name,
+            token => return Err(format!("Unexpected {token:?} in guard").into()),
+        };
+        let data = self
+            .state_types
+            .state_type_named(&name)
+            .ok_or_else(|| format!("No state variable named {name}"))?;
+
+        let Some(Token::Op(op)) = self.peek().cloned() else {
+            check_truthy(data)?;
+            return Ok(Guard::Variable(data.id.clone()));
+        };
+        self.position += 1;
+        let value = match (self.next()?, &data.state_type) {
+            (Token::Number(number), _) => match number.parse::<i64>() {
+                Ok(value) => StateTypeValue::Int(value),
+                Err(_) => StateTypeValue::Float(
+                    number
+                        .parse()
+                        .map_err(|_| format!("Invalid number {number} in guard"))?,
+                ),
+            },
+            (Token::Identifier(word), _) if word == "true" || word == "false" => {
+                StateTypeValue::Bool(word == "true")
+            }
+            (Token::Identifier(variant) | Token::Quoted(variant), StateType::Enum(_)) => {
+                StateTypeValue::Enum(variant)
+            }
+            (token, _) => return Err(format!("Unexpected {token:?} in guard").into()),
+        };
+        check_comparison(data, op, &value)?;
+        Ok(Guard::Compare {
+            variable: data.id.clone(),
+            op,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> Vec<StateTypeData> {
+        vec![
+            StateTypeData::new("Grounded", StateType::Bool),
+            StateTypeData::new("Jump", StateType::Trigger),
+            StateTypeData::new("Falling", StateType::Bool),
+            StateTypeData::new(
+                "Speed",
+                StateType::Float {
+                    min: Some(0.0),
+                    max: Some(10.0),
+                },
+            ),
+            StateTypeData::new("Lives", StateType::Int),
+            StateTypeData::new(
+                "Mode",
+                StateType::Enum(vec!["Idle".to_string(), "Run fast".to_string()]),
+            ),
+            StateTypeData::new("not", StateType::Bool),
+            StateTypeData::new("Is moving", StateType::Bool),
+        ]
+    }
+
+    fn variable(name: &str, state_types: &[StateTypeData]) -> Box<Guard> {
+        let data = state_types.iter().find(|data| data.name == name).unwrap();
+        Box::new(Guard::Variable(data.id.clone()))
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        let state_types = variables();
+        let [grounded, jump, falling] =
+            ["Grounded", "Jump", "Falling"].map(|name| variable(name, &state_types));
+        assert_eq!(
+            Guard::parse("Grounded or Jump and not Falling", &state_types).unwrap(),
+            Guard::Or(
+                grounded.clone(),
+                Box::new(Guard::And(
+                    jump.clone(),
+                    Box::new(Guard::Not(falling.clone()))
+                ))
+            )
+        );
+        assert_eq!(
+            Guard::parse("!Grounded && Jump || Falling", &state_types).unwrap(),
+            Guard::Or(
+                Box::new(Guard::And(
+                    Box::new(Guard::Not(grounded.clone())),
+                    jump.clone()
+                )),
+                falling.clone()
+            )
+        );
+        let grouped = Guard::And(Box::new(Guard::Or(grounded, jump)), falling);
+        assert_eq!(
+            Guard::parse("(Grounded or Jump) and Falling", &state_types).unwrap(),
+            grouped
+        );
+        assert_eq!(
+            grouped.display(&state_types),
+            "(Grounded or Jump) and Falling"
+        );
+    }
+
+    #[test]
+    fn keywords_and_names_with_spaces_are_quoted() {
+        let state_types = variables();
+        let guard = Guard::parse(
+            "\"not\" and not \"Is moving\" or Mode == \"Run fast\"",
+            &state_types,
+        )
+        .unwrap();
+        assert_eq!(
+            guard.display(&state_types),
+            "\"not\" and not \"Is moving\" or Mode == \"Run fast\""
+        );
+        assert!(Guard::parse("not and Grounded", &state_types).is_err());
+        assert_eq!(quote_if_needed("true"), "\"true\"");
+        assert_eq!(quote_if_needed("Say \"hi\""), "\"Say \\\"hi\\\"\"");
+        assert_eq!(quote_if_needed("Run_2"), "Run_2");
+    }
+
+    #[test]
+    fn display_parses_back_to_the_same_guard() {
+        let state_types = variables();
+        for source in [
+            "true",
+            "not (Grounded and Jump)",
+            "not not Falling",
+            "Grounded and (Jump or Falling) and Lives >= 3",
+            "(Grounded or Jump) and (Falling or \"Is moving\")",
+            "Speed < 2.5 or Speed == 10 and Mode != Idle",
+            "Lives > -1 and not (Mode == \"Run fast\" or \"not\")",
+        ] {
+            let guard = Guard::parse(source, &state_types).unwrap();
+            let display = guard.display(&state_types);
+            assert_eq!(
+                Guard::parse(&display, &state_types).unwrap(),
+                guard,
+                "{source} was displayed as {display}"
+            );
+        }
+    }
+
+    #[test]
+    fn renamed_and_removed_variables() {
+        let mut state_types = variables();
+        let guard = Guard::parse("Grounded and Lives > 0", &state_types).unwrap();
+        state_types[0].name = "On floor".to_string();
+        assert_eq!(guard.display(&state_types), "\"On floor\" and Lives > 0");
+        assert!(guard.problems(&state_types).is_empty());
+
+        state_types[4].state_type = StateType::Enum(vec!["Zero".to_string()]);
+        assert_eq!(
+            guard.problems(&state_types),
+            ["Lives is a Enum and can't be compared with >"]
+        );
+
+        state_types.remove(0);
+        assert_eq!(guard.display(&state_types), "<missing> and Lives > 0");
+        assert_eq!(
+            guard.problems(&state_types),
+            [
+                "Reads a state variable that no longer exists",
+                "Lives is a Enum and can't be compared with >"
+            ]
+        );
+    }
+
+    #[test]
+    fn can_hold_tries_the_values_comparisons_tell_apart() {
+        let state_types = variables();
+        let can_hold = |source: &str| {
+            Guard::parse(source, &state_types)
+                .unwrap()
+                .can_hold(&state_types, &HashMap::default())
+        };
+        assert!(can_hold("Speed > 3 and Speed < 3.5"));
+        assert!(!can_hold("Speed > 3 and Speed < 3"));
+        assert!(!can_hold("Speed > 10"));
+        assert!(!can_hold("Lives > 3 and Lives < 4"));
+        assert!(can_hold("Lives > 3 and Lives < 5"));
+        assert!(!can_hold("Mode == Idle and Mode == \"Run fast\""));
+
+        let grounded = &state_types[0];
+        let fixed = HashMap::from_iter([(grounded.id.clone(), StateTypeValue::Bool(false))]);
+        let guard = Guard::parse("Grounded or Jump", &state_types).unwrap();
+        assert!(guard.can_hold(&state_types, &fixed));
+        let guard = Guard::parse("Grounded and Jump", &state_types).unwrap();
+        assert!(!guard.can_hold(&state_types, &fixed));
+    }
+
+    #[test]
+    fn names_with_quotes_and_backslashes_round_trip() {
+        let state_types = vec![
+            StateTypeData::new("Say \"hi\"", StateType::Bool),
+            StateTypeData::new("back\\slash", StateType::Bool),
+        ];
+        let guard = Guard::And(
+            Box::new(Guard::Variable(state_types[0].id.clone())),
+            Box::new(Guard::Variable(state_types[1].id.clone())),
+        );
+        let display = guard.display(&state_types);
+        assert_eq!(display, r#""Say \"hi\"" and "back\\slash""#);
+        assert_eq!(Guard::parse(&display, &state_types).unwrap(), guard);
+    }
+
+    #[test]
+    fn huge_int_literals_dont_overflow() {
+        let state_types = variables();
+        let can_hold = |source: &str| {
+            Guard::parse(source, &state_types)
+                .unwrap()
+                .can_hold(&state_types, &HashMap::default())
+        };
+        assert!(!can_hold("Lives > 9223372036854775807"));
+        assert!(!can_hold("Lives < -9223372036854775808"));
+        assert!(can_hold("Lives >= 9223372036854775807"));
+    }
+
+    #[test]
+    fn can_hold_gives_up_past_max_combinations() {
+        let state_types = (0..8)
+            .map(|index| StateTypeData::new(format!("V{index}"), StateType::Int))
+            .collect::<Vec<_>>();
+        // V0 can't be both, but each variable adds a factor of at least 3 to try
+        let impossible = |count: usize| {
+            let source = (1..count)
+                .map(|index| format!(" and V{index} == 1"))
+                .collect::<String>();
+            Guard::parse(&format!("V0 == 1 and V0 != 1{source}"), &state_types).unwrap()
+        };
+        assert!(!impossible(6).can_hold(&state_types, &HashMap::default()));
+        let too_many = impossible(8);
+        assert!(3usize.pow(8) > MAX_COMBINATIONS);
+        assert!(too_many.can_hold(&state_types, &HashMap::default()));
+    }
+}
@@ -0,0 +1,545 @@
+//! Nested states in the editor: grouping nodes into a state with children, and drilling into one
+//! to edit what's inside it.
+//!
+//! The canvas shows the children of one state at a time, the [`CanvasScope`]. States with
+//! children are drawn collapsed, as a single node listing what's inside them. The regions of a
+//! parallel state are drawn as [`Lanes`] stacked on top of each other instead, each showing the
+//! states in one region.
+
+use bevy::color::palettes::css;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use itertools::Itertools;
+
+use crate::bindings::no_bound_key_pressed;
+use crate::canvas::{Canvas, CanvasOffset, CanvasPosition};
+use crate::editor::{MainSpace, Selected};
+use crate::history::{Edit, History, Perform};
+use crate::text_input::{TextInputSystemSet, no_text_input_active};
+use crate::{State, StateNodeId, Transition};
+
+pub struct HierarchyPlugin;
+
+impl Plugin for HierarchyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CanvasScope>()
+            .init_resource::<Lanes>()
+            .add_systems(
+                Update,
+                (
+                    // Enter also submits text inputs, which mustn't drill in as well
+                    hierarchy_shortcuts
+                        .run_if(no_text_input_active.and(no_bound_key_pressed))
+                        .before(TextInputSystemSet),
+                    leave_removed_scope,
+                    layout_lanes,
+                    show_nodes_in_scope.after(layout_lanes),
+                    update_lanes.after(layout_lanes),
+                    update_breadcrumb,
+                ),
+            )
+            .add_observer(add_breadcrumb)
+            .add_observer(enter_scope);
+    }
+}
+
+/// The state whose children the canvas shows, or `None` for the top level.
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct CanvasScope(pub Option<StateNodeId>);
+
+/// Shows the children of a state on the canvas, or the top level for `None`.
+#[derive(Event, Debug)]
+pub struct EnterScope(pub Option<StateNodeId>);
+
+fn enter_scope(
+    trigger: Trigger<EnterScope>,
+    mut scope: ResMut<CanvasScope>,
+    states: Query<&State>,
+    selected: Query<(Entity, &State), With<Selected>>,
+    mut commands: Commands,
+) {
+    scope.set_if_neq(CanvasScope(trigger.0.clone()));
+    let parallel = states
+        .iter()
+        .any(|state| Some(&state.id) == trigger.0.as_ref() && state.parallel);
+    // Nodes that aren't shown anymore can't be selected either
+    for (node, state) in selected.iter() {
+        let in_region = parallel
+            && states.iter().any(|region| {
+                Some(&region.id) == state.parent.as_ref() && region.parent == trigger.0
+            });
+        if state.parent != trigger.0 && !in_region {
+            commands.entity(node).remove::<Selected>();
+        }
+    }
+}
+
+/// Goes back to the top level when the state being shown is deleted, e.g. by undoing its
+/// creation.
+fn leave_removed_scope(scope: Res<CanvasScope>, states: Query<&State>, mut commands: Commands) {
+    if let Some(id) = &scope.0
+        && !states.iter().any(|state| state.id == *id)
+    {
+        commands.trigger(EnterScope(None));
+    }
+}
+
+/// Room above the states in a lane for the name of its region.
+const LANE_HEADER: f32 = 40.0;
+/// How much bigger a lane is than the states in it, so there's room to add more.
+const LANE_MARGIN: Vec2 = Vec2::new(400.0, 200.0);
+const LANE_MIN_SIZE: Vec2 = Vec2::new(1000.0, 250.0);
+const LANE_GAP: f32 = 20.0;
+
+/// One region of a parallel [`CanvasScope`], drawn as a lane the states in it are shown in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LaneLayout {
+    pub region: StateNodeId,
+    pub name: String,
+    /// The whole lane in canvas coordinates, including its header.
+    pub rect: Rect,
+}
+
+impl LaneLayout {
+    /// How far the states in the region are moved from their [`CanvasPosition`].
+    pub fn offset(&self) -> Vec2 {
+        self.rect.min + Vec2::new(0.0, LANE_HEADER)
+    }
+}
+
+/// The regions of the [`CanvasScope`] stacked top to bottom when it's a parallel state, empty
+/// otherwise.
+#[derive(Resource, Debug, Default, Clone, PartialEq)]
+pub struct Lanes(pub Vec<LaneLayout>);
+
+impl Lanes {
+    pub fn region(&self, id: &StateNodeId) -> Option<&LaneLayout> {
+        self.0.iter().find(|lane| lane.region == *id)
+    }
+
+    /// The lane containing `position`, in canvas coordinates.
+    pub fn at(&self, position: Vec2) -> Option<&LaneLayout> {
+        self.0.iter().find(|lane| lane.rect.contains(position))
+    }
+}
+
+fn layout_lanes(
+    scope: Res<CanvasScope>,
+    states: Query<(Entity, &State, &CanvasPosition)>,
+    mut lanes: ResMut<Lanes>,
+) {
+    let parallel = states
+        .iter()
+        .any(|(_, state, _)| Some(&state.id) == scope.0.as_ref() && state.parallel);
+    let mut regions = states
+        .iter()
+        .filter(|(_, state, _)| parallel && state.parent == scope.0)
+        .collect::<Vec<_>>();
+    regions.sort_by_key(|(entity, ..)| *entity);
+
+    let mut top = 0.0;
+    let mut layout = regions
+        .into_iter()
+        .map(|(_, region, _)| {
+            let size = states
+                .iter()
+                .filter(|(_, state, _)| state.parent.as_ref() == Some(&region.id))
+                .map(|(.., position)| position.0 + LANE_MARGIN)
+                .fold(LANE_MIN_SIZE, Vec2::max);
+            let rect = Rect::new(0.0, top, size.x, top + LANE_HEADER + size.y);
+            top = rect.max.y + LANE_GAP;
+            LaneLayout {
+                region: region.id.clone(),
+                name: region.name.clone(),
+                rect,
+            }
+        })
+        .collect::<Vec<_>>();
+    // Stacked lanes line up, so they're all as wide as the widest one
+    let width = layout
+        .iter()
+        .map(|lane| lane.rect.max.x)
+        .fold(0.0, f32::max);
+    for lane in layout.iter_mut() {
+        lane.rect.max.x = width;
+    }
+    lanes.set_if_neq(Lanes(layout));
+}
+
+fn show_nodes_in_scope(
+    scope: Res<CanvasScope>,
+    lanes: Res<Lanes>,
+    nodes: Query<(&State, &mut Visibility, &mut CanvasOffset)>,
+) {
+    for (state, mut visibility, mut offset) in nodes {
+        let lane = state
+            .parent
+            .as_ref()
+            .and_then(|parent| lanes.region(parent));
+        let shown = lane.is_some() || (lanes.0.is_empty() && state.parent == scope.0);
+        visibility.set_if_neq(if shown {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+        offset.set_if_neq(CanvasOffset(
+            lane.map(LaneLayout::offset).unwrap_or_default(),
+        ));
+    }
+}
+
+/// Where states created on the canvas go.
+#[derive(SystemParam)]
+pub struct Placement<'w> {
+    scope: Res<'w, CanvasScope>,
+    lanes: Res<'w, Lanes>,
+}
+
+impl Placement<'_> {
+    /// The parent of a state created at `position` on the canvas, and its position in there: the
+    /// [`CanvasScope`], or the region whose lane `position` is in.
+    pub fn place(&self, position: Vec2) -> (Option<StateNodeId>, Vec2) {
+        match self.lanes.at(position) {
+            Some(lane) => (Some(lane.region.clone()), position - lane.offset()),
+            None => (self.scope.0.clone(), position),
+        }
+    }
+}
+
+/// The background of a region of a parallel state, see [`Lanes`].
+#[derive(Component)]
+struct Lane;
+
+/// Drills into the region it names when clicked.
+#[derive(Component)]
+struct LaneHeader(StateNodeId);
+
+fn update_lanes(
+    lanes: Res<Lanes>,
+    canvas: Res<Canvas>,
+    mut rendered_zoom: Local<f32>,
+    main_space: Query<Entity, With<MainSpace>>,
+    existing: Query<Entity, With<Lane>>,
+    mut commands: Commands,
+) -> Result {
+    // Lanes follow panning through their canvas position, but have to be resized when zooming
+    if !lanes.is_changed() && *rendered_zoom == canvas.zoom {
+        return Ok(());
+    }
+    *rendered_zoom = canvas.zoom;
+    let main_space = main_space.single()?;
+    for lane in existing.iter() {
+        commands.entity(lane).despawn();
+    }
+    for layout in lanes.0.iter() {
+        let lane = commands
+            .spawn((
+                Node {
+                    position_type: PositionType::Absolute,
+                    width: canvas.px(layout.rect.width()),
+                    height: canvas.px(layout.rect.height()),
+                    padding: UiRect::all(canvas.px(5.0)),
+                    border: UiRect::all(Val::Px(1.0)),
+                    align_items: AlignItems::FlexStart,
+                    ..default()
+                },
+                CanvasPosition(layout.rect.min),
+                BackgroundColor(css::DARK_SLATE_GRAY.with_alpha(0.4).into()),
+                BorderColor(css::GRAY.into()),
+                // Behind the nodes, and clicks go through to the canvas to create states
+                ZIndex(-1),
+                Pickable::IGNORE,
+                Lane,
+                ChildOf(main_space),
+            ))
+            .id();
+        commands
+            .spawn((
+                Node {
+                    padding: UiRect::horizontal(Val::Px(3.0)),
+                    ..default()
+                },
+                BackgroundColor(css::GRAY.with_alpha(0.3).into()),
+                BorderRadius::all(Val::Px(3.0)),
+                Button,
+                LaneHeader(layout.region.clone()),
+                ChildOf(lane),
+                children![(
+                    Text(layout.name.clone()),
+                    TextFont::from_font_size(canvas.scale(16.0)),
+                )],
+            ))
+            .observe(click_lane_header);
+    }
+    Ok(())
+}
+
+fn click_lane_header(
+    trigger: Trigger<Pointer<Click>>,
+    headers: Query<&LaneHeader>,
+    mut commands: Commands,
+) -> Result {
+    let LaneHeader(region) = headers.get(trigger.target())?;
+    commands.trigger(EnterScope(Some(region.clone())));
+    Ok(())
+}
+
+/// `G` groups the selected nodes into a new state in the state they're in, `Shift+G` moves the
+/// children of the selected states out and removes them. `Enter` drills into the selected state,
+/// which gives it children once some are created there, and `Shift+Enter` goes back out.
+fn hierarchy_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    scope: Res<CanvasScope>,
+    states: Query<(&State, &CanvasPosition, Has<Selected>)>,
+    transitions: Query<&Transition>,
+    mut commands: Commands,
+) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let mut selected = states
+        .iter()
+        .filter(|(.., selected)| *selected)
+        .map(|(state, position, _)| (state, position.0))
+        .collect::<Vec<_>>();
+    selected.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+
+    if keys.just_pressed(KeyCode::Enter) {
+        if shift {
+            let parent = scope.0.as_ref().and_then(|id| {
+                states
+                    .iter()
+                    .find(|(state, ..)| state.id == *id)
+                    .and_then(|(state, ..)| state.parent.clone())
+            });
+            if scope.0.is_some() {
+                commands.trigger(EnterScope(parent));
+            }
+        } else if let [(state, _)] = selected.as_slice() {
+            commands.trigger(EnterScope(Some(state.id.clone())));
+        }
+    }
+
+    if !keys.just_pressed(KeyCode::KeyG) || selected.is_empty() {
+        return;
+    }
+    if shift {
+        let edits = selected
+            .iter()
+            .flat_map(|(state, position)| {
+                let children = states
+                    .iter()
+                    .filter(|(child, ..)| child.parent.as_ref() == Some(&state.id))
+                    .map(|(child, position, _)| (child, position.0))
+                    .collect::<Vec<_>>();
+                ungroup(state, *position, &children, &transitions)
+            })
+            .collect::<Vec<_>>();
+        if !edits.is_empty() {
+            commands.queue(Perform(Edit::Batch(edits)));
+        }
+        return;
+    }
+
+    let name = (1..)
+        .map(|n| match n {
+            1 => "New Group".to_string(),
+            n => format!("New Group {n}"),
+        })
+        .find(|name| states.iter().all(|(state, ..)| state.name != *name))
+        .unwrap();
+    let group = State {
+        id: rand::random(),
+        name,
+        state: vec![],
+        // Nodes in the lanes of a parallel state are grouped inside their region
+        parent: selected
+            .iter()
+            .map(|(state, _)| state.parent.clone())
+            .all_equal_value()
+            .unwrap_or_else(|_| scope.0.clone()),
+        parallel: false,
+        history: None,
+        // Takes over being initial, and the child that was stays initial inside it
+        initial: selected.iter().any(|(state, _)| state.initial),
+        terminal: false,
+        on_entry: vec![],
+        on_exit: vec![],
+    };
+    let position = selected
+        .iter()
+        .map(|(_, position)| *position)
+        .reduce(Vec2::min)
+        .unwrap_or_default();
+    let mut edits = vec![Edit::AddNode {
+        state: group.clone(),
+        position,
+    }];
+    edits.extend(selected.iter().map(|(state, _)| Edit::SetParent {
+        node: state.id.clone(),
+        old: state.parent.clone(),
+        new: Some(group.id.clone()),
+    }));
+    if !group.initial {
+        edits.push(Edit::SetInitial {
+            old: None,
+            new: Some(selected[0].0.id.clone()),
+        });
+    }
+    debug!("Grouping {} states into {}", selected.len(), group.name);
+    commands.queue(Perform(Edit::Batch(edits)));
+}
+
+/// Moves the children of `state` to where it is and removes it, along with its transitions.
+fn ungroup(
+    state: &State,
+    position: Vec2,
+    children: &[(&State, Vec2)],
+    transitions: &Query<&Transition>,
+) -> Vec<Edit> {
+    if children.is_empty() {
+        return vec![];
+    }
+    let mut edits = vec![];
+    // Only one of the states in the parent can stay initial
+    let initial = children.iter().find(|(child, _)| child.initial);
+    match initial {
+        Some((child, _)) if !state.initial => edits.push(Edit::SetInitial {
+            old: Some(child.id.clone()),
+            new: None,
+        }),
+        None if state.initial => edits.push(Edit::SetInitial {
+            old: None,
+            new: Some(children[0].0.id.clone()),
+        }),
+        _ => {}
+    }
+    let origin = children
+        .iter()
+        .map(|(_, position)| *position)
+        .reduce(Vec2::min)
+        .unwrap_or_default();
+    for (child, child_position) in children {
+        edits.push(Edit::SetParent {
+            node: child.id.clone(),
+            old: child.parent.clone(),
+            new: state.parent.clone(),
+        });
+        edits.push(Edit::MoveNode {
+            node: child.id.clone(),
+            old: *child_position,
+            new: position + *child_position - origin,
+        });
+    }
+    edits.extend(
+        transitions
+            .iter()
+            .filter(|transition| transition.from == state.id || transition.to == state.id)
+            .map(|transition| Edit::RemoveTransition(transition.clone())),
+    );
+    edits.push(Edit::RemoveNode {
+        state: state.clone(),
+        position,
+    });
+    debug!("Ungrouping {}", state.name);
+    edits
+}
+
+/// Where the canvas is in the hierarchy, only shown below the top level.
+#[derive(Component)]
+struct Breadcrumb;
+
+/// Goes to the level it names when clicked.
+#[derive(Component)]
+struct BreadcrumbEntry(Option<StateNodeId>);
+
+fn add_breadcrumb(trigger: Trigger<OnAdd, MainSpace>, mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(10.0),
+            top: Val::Px(10.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            column_gap: Val::Px(5.0),
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        BackgroundColor(css::DARK_GRAY.with_alpha(0.9).into()),
+        BorderRadius::all(Val::Px(5.0)),
+        FocusPolicy::Block,
+        ZIndex(1),
+        Visibility::Hidden,
+        Breadcrumb,
+        ChildOf(trigger.target()),
+    ));
+}
+
+fn update_breadcrumb(
+    scope: Res<CanvasScope>,
+    history: Res<History>,
+    states: Query<&State>,
+    mut breadcrumbs: Query<(Entity, &mut Visibility), With<Breadcrumb>>,
+    mut commands: Commands,
+) {
+    // Grouping, ungrouping and undoing either go through the history
+    if !scope.is_changed() && !history.is_changed() {
+        return;
+    }
+    let count = states.iter().count();
+    let mut path = vec![];
+    let mut next = scope.0.clone();
+    while let Some(state) = next
+        .as_ref()
+        .and_then(|id| states.iter().find(|state| state.id == *id))
+        && path.len() < count
+    {
+        path.push((Some(state.id.clone()), state.name.clone()));
+        next = state.parent.clone();
+    }
+    path.push((None, "Top level".to_string()));
+    path.reverse();
+
+    for (breadcrumb, mut visibility) in breadcrumbs.iter_mut() {
+        *visibility = if scope.0.is_some() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        commands.entity(breadcrumb).despawn_related::<Children>();
+        for (index, (id, name)) in path.iter().enumerate() {
+            if index > 0 {
+                commands.spawn((
+                    Text(">".to_string()),
+                    TextFont::from_font_size(14.0),
+                    ChildOf(breadcrumb),
+                ));
+            }
+            commands
+                .spawn((
+                    Node {
+                        padding: UiRect::horizontal(Val::Px(3.0)),
+                        ..default()
+                    },
+                    BackgroundColor(css::GRAY.with_alpha(0.3).into()),
+                    BorderRadius::all(Val::Px(3.0)),
+                    Button,
+                    BreadcrumbEntry(id.clone()),
+                    ChildOf(breadcrumb),
+                    children![(Text(name.clone()), TextFont::from_font_size(14.0))],
+                ))
+                .observe(click_breadcrumb_entry);
+        }
+    }
+}
+
+fn click_breadcrumb_entry(
+    trigger: Trigger<Pointer<Click>>,
+    entries: Query<&BreadcrumbEntry>,
+    mut commands: Commands,
+) -> Result {
+    let BreadcrumbEntry(id) = entries.get(trigger.target())?;
+    commands.trigger(EnterScope(id.clone()));
+    Ok(())
+}
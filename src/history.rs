@@ -0,0 +1,800 @@
+use bevy::prelude::*;
+
+use crate::action::Action;
+use crate::bindings::{InputBinding, no_bound_key_pressed};
+use crate::canvas::CanvasPosition;
+use crate::document::DocumentLoaded;
+use crate::editor::{
+    MainSpace, StateTypeAdded, StateTypeChanged, StateTypeNameChanged, StateTypeRemoved,
+    spawn_state_node,
+};
+use crate::guard::Guard;
+use crate::text_input::no_text_input_active;
+use crate::{
+    HistoryKind, State, StateId, StateNodeId, StateType, StateTypeData, StateTypeValue, StateTypes,
+    StateValue, Transition,
+};
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<History>()
+            .add_systems(
+                Update,
+                undo_redo_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+            )
+            .add_observer(clear_history);
+    }
+}
+
+/// A reversible change to the document. Everything the editor changes goes through one of these,
+/// see [`Perform`].
+#[derive(Debug, Clone)]
+pub enum Edit {
+    /// Several edits that are undone and redone together.
+    Batch(Vec<Edit>),
+    RenameStateType {
+        state_type: StateId,
+        old: String,
+        new: String,
+    },
+    /// Only changes the type itself, values that no longer fit are changed with separate
+    /// [`Edit::SetValue`]s.
+    SetStateType {
+        state_type: StateId,
+        old: StateType,
+        new: StateType,
+    },
+    SetBinding {
+        state_type: StateId,
+        old: Option<InputBinding>,
+        new: Option<InputBinding>,
+    },
+    AddStateType {
+        data: StateTypeData,
+        values: Vec<(StateNodeId, StateValue)>,
+    },
+    RemoveStateType {
+        data: StateTypeData,
+        values: Vec<(StateNodeId, StateValue)>,
+    },
+    SetValue {
+        node: StateNodeId,
+        state_type: StateId,
+        old: StateTypeValue,
+        new: StateTypeValue,
+    },
+    /// Turns don't care on or off for a value, see [`StateValue::any`].
+    SetAny {
+        node: StateNodeId,
+        state_type: StateId,
+        any: bool,
+    },
+    MoveNode {
+        node: StateNodeId,
+        old: Vec2,
+        new: Vec2,
+    },
+    AddNode {
+        state: State,
+        position: Vec2,
+    },
+    RemoveNode {
+        state: State,
+        position: Vec2,
+    },
+    /// Moves the initial marker between states in the same parent, of which at most one should
+    /// have it.
+    SetInitial {
+        old: Option<StateNodeId>,
+        new: Option<StateNodeId>,
+    },
+    /// Nests a state in another one, or moves it to the top level.
+    SetParent {
+        node: StateNodeId,
+        old: Option<StateNodeId>,
+        new: Option<StateNodeId>,
+    },
+    SetTerminal {
+        node: StateNodeId,
+        terminal: bool,
+    },
+    /// Turns the states nested in a state into regions that are all active at once, or back.
+    SetParallel {
+        node: StateNodeId,
+        parallel: bool,
+    },
+    /// Turns a state into a history pseudo-state, or back for `None`.
+    SetHistory {
+        node: StateNodeId,
+        old: Option<HistoryKind>,
+        new: Option<HistoryKind>,
+    },
+    SetEntryActions {
+        node: StateNodeId,
+        old: Vec<Action>,
+        new: Vec<Action>,
+    },
+    SetExitActions {
+        node: StateNodeId,
+        old: Vec<Action>,
+        new: Vec<Action>,
+    },
+    AddTransition(Transition),
+    RemoveTransition(Transition),
+    SetGuard {
+        from: StateNodeId,
+        to: StateNodeId,
+        old: Option<Guard>,
+        new: Option<Guard>,
+    },
+    /// Changes how long the machine waits before taking a transition.
+    SetDelay {
+        from: StateNodeId,
+        to: StateNodeId,
+        old: Option<f32>,
+        new: Option<f32>,
+    },
+    SetTransitionActions {
+        from: StateNodeId,
+        to: StateNodeId,
+        old: Vec<Action>,
+        new: Vec<Action>,
+    },
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(Edit::inverse).collect()),
+            Edit::RenameStateType {
+                state_type,
+                old,
+                new,
+            } => Edit::RenameStateType {
+                state_type,
+                old: new,
+                new: old,
+            },
+            Edit::SetStateType {
+                state_type,
+                old,
+                new,
+            } => Edit::SetStateType {
+                state_type,
+                old: new,
+                new: old,
+            },
+            Edit::SetBinding {
+                state_type,
+                old,
+                new,
+            } => Edit::SetBinding {
+                state_type,
+                old: new,
+                new: old,
+            },
+            Edit::AddStateType { data, values } => Edit::RemoveStateType { data, values },
+            Edit::RemoveStateType { data, values } => Edit::AddStateType { data, values },
+            Edit::SetValue {
+                node,
+                state_type,
+                old,
+                new,
+            } => Edit::SetValue {
+                node,
+                state_type,
+                old: new,
+                new: old,
+            },
+            Edit::SetAny {
+                node,
+                state_type,
+                any,
+            } => Edit::SetAny {
+                node,
+                state_type,
+                any: !any,
+            },
+            Edit::MoveNode { node, old, new } => Edit::MoveNode {
+                node,
+                old: new,
+                new: old,
+            },
+            Edit::AddNode { state, position } => Edit::RemoveNode { state, position },
+            Edit::RemoveNode { state, position } => Edit::AddNode { state, position },
+            Edit::SetInitial { old, new } => Edit::SetInitial { old: new, new: old },
+            Edit::SetParent { node, old, new } => Edit::SetParent {
+                node,
+                old: new,
+                new: old,
+            },
+            Edit::SetTerminal { node, terminal } => Edit::SetTerminal {
+                node,
+                terminal: !terminal,
+            },
+            Edit::SetParallel { node, parallel } => Edit::SetParallel {
+                node,
+                parallel: !parallel,
+            },
+            Edit::SetHistory { node, old, new } => Edit::SetHistory {
+                node,
+                old: new,
+                new: old,
+            },
+            Edit::SetEntryActions { node, old, new } => Edit::SetEntryActions {
+                node,
+                old: new,
+                new: old,
+            },
+            Edit::SetExitActions { node, old, new } => Edit::SetExitActions {
+                node,
+                old: new,
+                new: old,
+            },
+            Edit::AddTransition(transition) => Edit::RemoveTransition(transition),
+            Edit::RemoveTransition(transition) => Edit::AddTransition(transition),
+            Edit::SetGuard { from, to, old, new } => Edit::SetGuard {
+                from,
+                to,
+                old: new,
+                new: old,
+            },
+            Edit::SetDelay { from, to, old, new } => Edit::SetDelay {
+                from,
+                to,
+                old: new,
+                new: old,
+            },
+            Edit::SetTransitionActions { from, to, old, new } => Edit::SetTransitionActions {
+                from,
+                to,
+                old: new,
+                new: old,
+            },
+        }
+    }
+
+    pub fn apply(&self, world: &mut World) -> Result {
+        match self {
+            Edit::Batch(edits) => {
+                for (index, edit) in edits.iter().enumerate() {
+                    if let Err(error) = edit.apply(world) {
+                        // Undo what was already applied so the batch happens all or not at all,
+                        // as far as that still works
+                        for applied in edits[..index].iter().rev() {
+                            if let Err(error) = applied.inverse().apply(world) {
+                                warn!("Couldn't roll back {applied:?}: {error}");
+                            }
+                        }
+                        return Err(error);
+                    }
+                }
+            }
+            Edit::RenameStateType {
+                state_type, new, ..
+            } => {
+                let mut state_types = world.resource_mut::<StateTypes>();
+                let data = state_types
+                    .get_mut(state_type)
+                    .ok_or("StateType not found")?;
+                data.name = new.clone();
+                world.trigger(StateTypeNameChanged {
+                    state_type: state_type.clone(),
+                    name: new.clone(),
+                });
+            }
+            Edit::SetStateType {
+                state_type, new, ..
+            } => {
+                let mut state_types = world.resource_mut::<StateTypes>();
+                let data = state_types
+                    .get_mut(state_type)
+                    .ok_or("StateType not found")?;
+                data.state_type = new.clone();
+                world.trigger(StateTypeChanged {
+                    state_type: state_type.clone(),
+                });
+            }
+            Edit::SetBinding {
+                state_type, new, ..
+            } => {
+                let mut state_types = world.resource_mut::<StateTypes>();
+                let data = state_types
+                    .get_mut(state_type)
+                    .ok_or("StateType not found")?;
+                data.binding = *new;
+                world.trigger(StateTypeChanged {
+                    state_type: state_type.clone(),
+                });
+            }
+            Edit::AddStateType { data, values } => {
+                for (node, value) in values {
+                    let entity = state_entity(world, node)?;
+                    let mut state = world.get_mut::<State>(entity).ok_or("State not found")?;
+                    state.state.push(value.clone());
+                }
+                world.resource_mut::<StateTypes>().insert(data.clone());
+                world.trigger(StateTypeAdded {
+                    state_type: data.id.clone(),
+                });
+            }
+            Edit::RemoveStateType { data, values } => {
+                for (node, _) in values {
+                    let entity = state_entity(world, node)?;
+                    let mut state = world.get_mut::<State>(entity).ok_or("State not found")?;
+                    state.state.retain(|value| value.state != data.id);
+                }
+                world
+                    .resource_mut::<StateTypes>()
+                    .remove(&data.id)
+                    .ok_or("StateType not found")?;
+                world.trigger(StateTypeRemoved {
+                    state_type: data.id.clone(),
+                });
+            }
+            Edit::SetValue {
+                node,
+                state_type,
+                new,
+                ..
+            } => {
+                let entity = state_entity(world, node)?;
+                let mut state = world.get_mut::<State>(entity).ok_or("State not found")?;
+                state
+                    .state
+                    .iter_mut()
+                    .find(|value| value.state == *state_type)
+                    .ok_or("StateValue not found")?
+                    .value = new.clone();
+            }
+            Edit::SetAny {
+                node,
+                state_type,
+                any,
+            } => {
+                let entity = state_entity(world, node)?;
+                let mut state = world.get_mut::<State>(entity).ok_or("State not found")?;
+                state
+                    .state
+                    .iter_mut()
+                    .find(|value| value.state == *state_type)
+                    .ok_or("StateValue not found")?
+                    .any = *any;
+            }
+            Edit::MoveNode { node, new, .. } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<CanvasPosition>(entity)
+                    .ok_or("CanvasPosition not found")?
+                    .0 = *new;
+            }
+            Edit::AddNode { state, position } => {
+                let main_space = world
+                    .query_filtered::<Entity, With<MainSpace>>()
+                    .single(world)?;
+                spawn_state_node(&mut world.commands(), main_space, *position, state.clone());
+                world.flush();
+            }
+            Edit::RemoveNode { state, .. } => {
+                let entity = state_entity(world, &state.id)?;
+                world.despawn(entity);
+            }
+            Edit::SetInitial { old, new } => {
+                for (node, initial) in [(old, false), (new, true)] {
+                    let Some(node) = node else {
+                        continue;
+                    };
+                    let entity = state_entity(world, node)?;
+                    world
+                        .get_mut::<State>(entity)
+                        .ok_or("State not found")?
+                        .initial = initial;
+                }
+            }
+            Edit::SetParent { node, new, .. } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .parent = new.clone();
+            }
+            Edit::SetTerminal { node, terminal } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .terminal = *terminal;
+            }
+            Edit::SetParallel { node, parallel } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .parallel = *parallel;
+            }
+            Edit::SetHistory { node, new, .. } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .history = *new;
+            }
+            Edit::SetEntryActions { node, new, .. } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .on_entry = new.clone();
+            }
+            Edit::SetExitActions { node, new, .. } => {
+                let entity = state_entity(world, node)?;
+                world
+                    .get_mut::<State>(entity)
+                    .ok_or("State not found")?
+                    .on_exit = new.clone();
+            }
+            Edit::AddTransition(transition) => {
+                world.spawn(transition.clone());
+            }
+            Edit::RemoveTransition(transition) => {
+                let entity = transition_entity(world, &transition.from, &transition.to)?;
+                world.despawn(entity);
+            }
+            Edit::SetGuard { from, to, new, .. } => {
+                let entity = transition_entity(world, from, to)?;
+                world
+                    .get_mut::<Transition>(entity)
+                    .ok_or("Transition not found")?
+                    .guard = new.clone();
+            }
+            Edit::SetDelay { from, to, new, .. } => {
+                let entity = transition_entity(world, from, to)?;
+                world
+                    .get_mut::<Transition>(entity)
+                    .ok_or("Transition not found")?
+                    .after = *new;
+            }
+            Edit::SetTransitionActions { from, to, new, .. } => {
+                let entity = transition_entity(world, from, to)?;
+                world
+                    .get_mut::<Transition>(entity)
+                    .ok_or("Transition not found")?
+                    .actions = new.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn state_entity(world: &mut World, id: &StateNodeId) -> Result<Entity> {
+    Ok(world
+        .query::<(Entity, &State)>()
+        .iter(world)
+        .find(|(_, state)| state.id == *id)
+        .ok_or("State not found")?
+        .0)
+}
+
+fn transition_entity(world: &mut World, from: &StateNodeId, to: &StateNodeId) -> Result<Entity> {
+    Ok(world
+        .query::<(Entity, &Transition)>()
+        .iter(world)
+        .find(|(_, transition)| transition.from == *from && transition.to == *to)
+        .ok_or("Transition not found")?
+        .0)
+}
+
+/// Edits that can be undone, and undone edits that can be redone.
+#[derive(Resource, Debug, Default)]
+pub struct History {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+}
+
+impl History {
+    /// Remembers an edit that has already been applied, e.g. by dragging a node around.
+    pub fn record(&mut self, edit: Edit) {
+        self.undo.push(edit);
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+fn clear_history(_trigger: Trigger<DocumentLoaded>, mut history: ResMut<History>) {
+    history.clear();
+}
+
+/// Applies an [`Edit`] and records it in the [`History`].
+pub struct Perform(pub Edit);
+
+impl Command<Result> for Perform {
+    fn apply(self, world: &mut World) -> Result {
+        self.0.apply(world)?;
+        world.resource_mut::<History>().record(self.0);
+        Ok(())
+    }
+}
+
+/// Undoes the last edit. One that can't be undone stays where it is, so nothing is lost.
+pub fn undo(world: &mut World) {
+    let Some(edit) = world.resource::<History>().undo.last().cloned() else {
+        return;
+    };
+    debug!("Undoing {:?}", edit);
+    if let Err(error) = edit.inverse().apply(world) {
+        warn!("Couldn't undo {edit:?}: {error}");
+        return;
+    }
+    let mut history = world.resource_mut::<History>();
+    history.undo.pop();
+    history.redo.push(edit);
+}
+
+/// Redoes the last undone edit, like [`undo`].
+pub fn redo(world: &mut World) {
+    let Some(edit) = world.resource::<History>().redo.last().cloned() else {
+        return;
+    };
+    debug!("Redoing {:?}", edit);
+    if let Err(error) = edit.apply(world) {
+        warn!("Couldn't redo {edit:?}: {error}");
+        return;
+    }
+    let mut history = world.resource_mut::<History>();
+    history.redo.pop();
+    history.undo.push(edit);
+}
+
+fn undo_redo_shortcuts(keys: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if keys.just_pressed(KeyCode::KeyZ) && !shift {
+        commands.queue(undo);
+    } else if keys.just_pressed(KeyCode::KeyZ) || keys.just_pressed(KeyCode::KeyY) {
+        commands.queue(redo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::document::fixtures::{guarded, state, transition};
+    use crate::document::{CurrentDocument, DocumentState};
+
+    fn node(state: &DocumentState) -> Edit {
+        let state = state.clone();
+        Edit::AddNode {
+            state: State {
+                id: state.id,
+                name: state.name,
+                state: state.state,
+                parent: state.parent,
+                parallel: state.parallel,
+                history: state.history,
+                initial: state.initial,
+                terminal: state.terminal,
+                on_entry: state.on_entry,
+                on_exit: state.on_exit,
+            },
+            position: state.position,
+        }
+    }
+
+    /// Everything edits change, in an order that doesn't depend on entities.
+    fn snapshot(world: &mut World) -> String {
+        let mut document = world
+            .run_system_once(|current: CurrentDocument| current.document())
+            .unwrap();
+        document.states.sort_by(|a, b| a.name.cmp(&b.name));
+        document
+            .transitions
+            .sort_by_key(|transition| format!("{transition:?}"));
+        document.to_ron().unwrap()
+    }
+
+    #[test]
+    fn every_edit_is_undone_by_its_inverse() {
+        let speed = StateTypeData::new("Speed", StateType::Int);
+        let jump = StateTypeData::new("Jump", StateType::Trigger);
+        let grounded = StateTypeData::new("Grounded", StateType::Bool);
+        let state_types = vec![speed.clone(), jump.clone()];
+        let parent = state("Parent", None, true);
+        let mut first = state("First", Some(&parent), true);
+        first.state.push(StateValue {
+            state: speed.id.clone(),
+            value: StateTypeValue::Int(1),
+            any: false,
+        });
+        let second = state("Second", Some(&parent), false);
+        let third = state("Third", None, false);
+        let jumps = guarded(&first, &second, "Jump", &state_types);
+
+        let mut world = World::new();
+        world.init_resource::<History>();
+        world.insert_resource(StateTypes::default());
+        world.spawn(MainSpace);
+        let setup = [speed.clone(), jump.clone()]
+            .into_iter()
+            .map(|data| Edit::AddStateType {
+                data,
+                values: vec![],
+            })
+            .chain([&parent, &first, &second].map(node))
+            .chain([Edit::AddTransition(jumps.clone())]);
+        for edit in setup {
+            edit.apply(&mut world).unwrap();
+        }
+
+        let speed_value = first.state[0].clone();
+        let emit = vec![Action::Emit("Landed".into())];
+        let edits = vec![
+            Edit::RenameStateType {
+                state_type: speed.id.clone(),
+                old: "Speed".into(),
+                new: "Pace".into(),
+            },
+            Edit::SetStateType {
+                state_type: speed.id.clone(),
+                old: StateType::Int,
+                new: StateType::Float {
+                    min: None,
+                    max: None,
+                },
+            },
+            Edit::SetBinding {
+                state_type: jump.id.clone(),
+                old: None,
+                new: Some(InputBinding::Key(KeyCode::Space)),
+            },
+            Edit::AddStateType {
+                data: grounded.clone(),
+                values: vec![(
+                    first.id.clone(),
+                    StateValue {
+                        state: grounded.id.clone(),
+                        value: StateTypeValue::Bool(true),
+                        any: false,
+                    },
+                )],
+            },
+            Edit::RemoveStateType {
+                data: speed.clone(),
+                values: vec![(first.id.clone(), speed_value)],
+            },
+            Edit::SetValue {
+                node: first.id.clone(),
+                state_type: speed.id.clone(),
+                old: StateTypeValue::Int(1),
+                new: StateTypeValue::Int(2),
+            },
+            Edit::SetAny {
+                node: first.id.clone(),
+                state_type: speed.id.clone(),
+                any: true,
+            },
+            Edit::MoveNode {
+                node: first.id.clone(),
+                old: Vec2::ZERO,
+                new: Vec2::new(10.0, 20.0),
+            },
+            node(&third),
+            Edit::RemoveNode {
+                state: match node(&second) {
+                    Edit::AddNode { state, .. } => state,
+                    _ => unreachable!(),
+                },
+                position: Vec2::ZERO,
+            },
+            Edit::SetInitial {
+                old: Some(first.id.clone()),
+                new: Some(second.id.clone()),
+            },
+            Edit::SetParent {
+                node: second.id.clone(),
+                old: Some(parent.id.clone()),
+                new: None,
+            },
+            Edit::SetTerminal {
+                node: second.id.clone(),
+                terminal: true,
+            },
+            Edit::SetParallel {
+                node: parent.id.clone(),
+                parallel: true,
+            },
+            Edit::SetHistory {
+                node: second.id.clone(),
+                old: None,
+                new: Some(HistoryKind::Deep),
+            },
+            Edit::SetEntryActions {
+                node: second.id.clone(),
+                old: vec![],
+                new: emit.clone(),
+            },
+            Edit::SetExitActions {
+                node: first.id.clone(),
+                old: vec![],
+                new: emit.clone(),
+            },
+            Edit::AddTransition(transition(&second, &first)),
+            Edit::RemoveTransition(jumps.clone()),
+            Edit::SetGuard {
+                from: first.id.clone(),
+                to: second.id.clone(),
+                old: jumps.guard.clone(),
+                new: None,
+            },
+            Edit::SetDelay {
+                from: first.id.clone(),
+                to: second.id.clone(),
+                old: None,
+                new: Some(1.5),
+            },
+            Edit::SetTransitionActions {
+                from: first.id.clone(),
+                to: second.id.clone(),
+                old: vec![],
+                new: emit,
+            },
+            Edit::Batch(vec![
+                Edit::SetTerminal {
+                    node: first.id.clone(),
+                    terminal: true,
+                },
+                Edit::MoveNode {
+                    node: first.id.clone(),
+                    old: Vec2::ZERO,
+                    new: Vec2::ONE,
+                },
+            ]),
+        ];
+        for edit in edits {
+            let before = snapshot(&mut world);
+            edit.apply(&mut world).unwrap();
+            assert_ne!(snapshot(&mut world), before, "{edit:?} changed nothing");
+            edit.inverse().apply(&mut world).unwrap();
+            assert_eq!(snapshot(&mut world), before, "{edit:?} wasn't undone");
+        }
+    }
+
+    #[test]
+    fn failed_batch_is_rolled_back_and_failed_undo_kept() {
+        let start = state("Start", None, true);
+        let missing = state("Missing", None, false);
+        let mut world = World::new();
+        world.init_resource::<History>();
+        world.insert_resource(StateTypes::default());
+        world.spawn(MainSpace);
+        node(&start).apply(&mut world).unwrap();
+        let before = snapshot(&mut world);
+
+        let batch = Edit::Batch(vec![
+            Edit::SetTerminal {
+                node: start.id.clone(),
+                terminal: true,
+            },
+            Edit::SetTerminal {
+                node: missing.id.clone(),
+                terminal: true,
+            },
+        ]);
+        assert!(batch.apply(&mut world).is_err());
+        assert_eq!(snapshot(&mut world), before);
+
+        world.resource_mut::<History>().record(batch);
+        undo(&mut world);
+        assert_eq!(world.resource::<History>().undo.len(), 1);
+        assert!(world.resource::<History>().redo.is_empty());
+    }
+}
@@ -0,0 +1,20 @@
+pub mod action;
+pub mod bindings;
+pub mod canvas;
+pub mod codegen;
+pub mod coverage;
+pub mod document;
+pub mod editor;
+pub mod export;
+pub mod guard;
+pub mod hierarchy;
+pub mod history;
+pub mod model;
+pub mod runtime;
+pub mod scxml;
+pub mod simulation;
+pub mod text_input;
+pub mod validation;
+
+pub use editor::StateMachineEditorPlugin;
+pub use model::*;
@@ -0,0 +1,385 @@
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use itertools::Itertools;
+use rand::distr::{Distribution, StandardUniform};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::action::Action;
+use crate::bindings::InputBinding;
+use crate::guard::Guard;
+
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct StateTypes(HashMap<StateId, StateTypeData>);
+
+impl StateTypes {
+    pub fn insert(&mut self, state_type: StateTypeData) {
+        self.0.insert(state_type.id.clone(), state_type);
+    }
+}
+
+/// Anything state types can be looked up in, so the same checks work on the editor's
+/// [`StateTypes`] and on a saved [`crate::document::Document`].
+pub trait StateTypeLookup {
+    fn state_type(&self, id: &StateId) -> Option<&StateTypeData>;
+
+    fn state_type_named(&self, name: &str) -> Option<&StateTypeData>;
+}
+
+impl StateTypeLookup for StateTypes {
+    fn state_type(&self, id: &StateId) -> Option<&StateTypeData> {
+        self.0.get(id)
+    }
+
+    fn state_type_named(&self, name: &str) -> Option<&StateTypeData> {
+        self.0.values().find(|data| data.name == name)
+    }
+}
+
+impl StateTypeLookup for Vec<StateTypeData> {
+    fn state_type(&self, id: &StateId) -> Option<&StateTypeData> {
+        self.iter().find(|data| data.id == *id)
+    }
+
+    fn state_type_named(&self, name: &str) -> Option<&StateTypeData> {
+        self.iter().find(|data| data.name == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StateId(Uuid);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StateType {
+    Bool,
+    Int,
+    Float {
+        min: Option<f32>,
+        max: Option<f32>,
+    },
+    Enum(Vec<String>),
+    /// Set for a single step and then cleared again by the runtime.
+    Trigger,
+}
+
+impl StateType {
+    /// One of each kind of type, with default parameters, for picking the type of a new variable.
+    pub fn kinds() -> [StateType; 5] {
+        [
+            StateType::Bool,
+            StateType::Int,
+            StateType::Float {
+                min: None,
+                max: None,
+            },
+            StateType::Enum(vec!["A".to_string(), "B".to_string()]),
+            StateType::Trigger,
+        ]
+    }
+
+    pub fn default_value(&self) -> StateTypeValue {
+        match self {
+            StateType::Bool => StateTypeValue::Bool(false),
+            StateType::Int => StateTypeValue::Int(0),
+            StateType::Float { .. } => self.coerce(StateTypeValue::Float(0.0)),
+            StateType::Enum(variants) => {
+                StateTypeValue::Enum(variants.first().cloned().unwrap_or_default())
+            }
+            StateType::Trigger => StateTypeValue::Trigger(false),
+        }
+    }
+
+    pub fn accepts(&self, value: &StateTypeValue) -> bool {
+        match (self, value) {
+            (StateType::Bool, StateTypeValue::Bool(_))
+            | (StateType::Int, StateTypeValue::Int(_))
+            | (StateType::Trigger, StateTypeValue::Trigger(_)) => true,
+            (StateType::Float { min, max }, StateTypeValue::Float(value)) => {
+                min.is_none_or(|min| *value >= min) && max.is_none_or(|max| *value <= max)
+            }
+            (StateType::Enum(variants), StateTypeValue::Enum(variant)) => {
+                variants.contains(variant)
+            }
+            _ => false,
+        }
+    }
+
+    /// Turns `value` into the closest value this type accepts, e.g. after the type was edited.
+    pub fn coerce(&self, value: StateTypeValue) -> StateTypeValue {
+        match (self, value) {
+            (StateType::Float { min, max }, StateTypeValue::Float(value)) => StateTypeValue::Float(
+                value
+                    .max(min.unwrap_or(f32::NEG_INFINITY))
+                    .min(max.unwrap_or(f32::INFINITY)),
+            ),
+            (_, value) if self.accepts(&value) => value,
+            _ => self.default_value(),
+        }
+    }
+
+    pub fn parse_value(&self, text: &str) -> Option<StateTypeValue> {
+        let text = text.trim();
+        let value = match self {
+            StateType::Bool => StateTypeValue::Bool(text.parse().ok()?),
+            StateType::Int => StateTypeValue::Int(text.parse().ok()?),
+            StateType::Float { .. } => StateTypeValue::Float(text.parse().ok()?),
+            StateType::Enum(_) => StateTypeValue::Enum(text.to_string()),
+            StateType::Trigger => StateTypeValue::Trigger(text.parse().ok()?),
+        };
+        self.accepts(&value).then_some(value)
+    }
+
+    /// The editable parameters of this type, in the format [`StateType::with_params`] reads.
+    pub fn params(&self) -> Option<String> {
+        match self {
+            StateType::Float { min, max } => Some(format!(
+                "{}..{}",
+                min.map(|min| min.to_string()).unwrap_or_default(),
+                max.map(|max| max.to_string()).unwrap_or_default()
+            )),
+            StateType::Enum(variants) => Some(variants.join(", ")),
+            _ => None,
+        }
+    }
+
+    /// Parses `min..max` for floats (either side may be left out) and a comma separated list of
+    /// variants for enums.
+    pub fn with_params(&self, text: &str) -> Option<StateType> {
+        match self {
+            StateType::Float { .. } => {
+                let (min, max) = text.split_once("..")?;
+                let bound = |bound: &str| -> Option<Option<f32>> {
+                    let bound = bound.trim();
+                    if bound.is_empty() {
+                        Some(None)
+                    } else {
+                        bound.parse().ok().map(Some)
+                    }
+                };
+                let (min, max) = (bound(min)?, bound(max)?);
+                if let (Some(min), Some(max)) = (min, max)
+                    && min > max
+                {
+                    return None;
+                }
+                Some(StateType::Float { min, max })
+            }
+            StateType::Enum(_) => {
+                let variants = text
+                    .split(',')
+                    .map(|variant| variant.trim().to_string())
+                    .filter(|variant| !variant.is_empty())
+                    .unique()
+                    .collect::<Vec<_>>();
+                (!variants.is_empty()).then_some(StateType::Enum(variants))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StateType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateType::Bool => write!(f, "Bool"),
+            StateType::Int => write!(f, "Int"),
+            StateType::Float { .. } => write!(f, "Float"),
+            StateType::Enum(_) => write!(f, "Enum"),
+            StateType::Trigger => write!(f, "Trigger"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StateTypeValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    Enum(String),
+    Trigger(bool),
+}
+
+impl std::fmt::Display for StateTypeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateTypeValue::Bool(value) | StateTypeValue::Trigger(value) => write!(f, "{value}"),
+            StateTypeValue::Int(value) => write!(f, "{value}"),
+            StateTypeValue::Float(value) => write!(f, "{value}"),
+            StateTypeValue::Enum(variant) => write!(f, "{variant}"),
+        }
+    }
+}
+
+impl Distribution<StateId> for StandardUniform {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> StateId {
+        StateId(Uuid::from_u128(rng.random()))
+    }
+}
+
+/// Identifies a [`State`] node independently of the entity it's currently spawned as.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StateNodeId(Uuid);
+
+impl Distribution<StateNodeId> for StandardUniform {
+    fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> StateNodeId {
+        StateNodeId(Uuid::from_u128(rng.random()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateTypeData {
+    pub id: StateId,
+    pub name: String,
+    pub state_type: StateType,
+    /// What drives this while the machine is played in the editor.
+    #[serde(default)]
+    pub binding: Option<InputBinding>,
+}
+
+impl StateTypeData {
+    pub fn new(name: impl ToString, state_type: StateType) -> Self {
+        Self {
+            id: rand::random(),
+            name: name.to_string(),
+            state_type,
+            binding: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateValue {
+    pub state: StateId,
+    pub value: StateTypeValue,
+    /// Don't care: any value of the variable matches. `value` is kept for when this is turned
+    /// off again.
+    #[serde(default)]
+    pub any: bool,
+}
+
+impl StateValue {
+    pub fn new(state: StateId, value: StateTypeValue) -> Self {
+        Self {
+            state,
+            value,
+            any: false,
+        }
+    }
+
+    pub fn matches(&self, value: &StateTypeValue) -> bool {
+        self.any || self.value == *value
+    }
+}
+
+#[derive(Component, Clone, Debug)]
+pub struct State {
+    pub id: StateNodeId,
+    pub name: String,
+    pub state: Vec<StateValue>,
+    /// The composite state this one is nested in. Composites are the states that other states
+    /// name as their parent.
+    pub parent: Option<StateNodeId>,
+    /// Whether the states nested in this one are regions that are all active at once, instead
+    /// of alternatives.
+    pub parallel: bool,
+    /// Makes this a history pseudo-state of its parent instead of a state the machine can be in.
+    /// Transitions to it resume the parent where it was left.
+    pub history: Option<HistoryKind>,
+    /// Where the machine starts, or where it goes when entering the parent. Only one state per
+    /// parent should be initial.
+    pub initial: bool,
+    /// The machine stops once it gets here, any transitions out of it are never taken.
+    pub terminal: bool,
+    /// Run whenever the machine enters this state, after the actions of the transition.
+    pub on_entry: Vec<Action>,
+    /// Run whenever the machine leaves this state, before the actions of the transition.
+    pub on_exit: Vec<Action>,
+}
+
+/// What a history pseudo-state resumes when a transition goes to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryKind {
+    /// The child of the parent that was active when it was left, entered as usual from there.
+    Shallow,
+    /// Every state nested in the parent that was active when it was left.
+    Deep,
+}
+
+/// A connection between two states. This doesn't reference any UI entities, so it survives the
+/// editor re-rendering the nodes on either end.
+#[derive(Component, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub from: StateNodeId,
+    pub to: StateNodeId,
+    #[serde(default)]
+    pub guard: Option<Guard>,
+    /// How many seconds the machine has to have been in `from` before this is taken, on top of
+    /// the guard holding.
+    #[serde(default)]
+    pub after: Option<f32>,
+    /// Run when this is taken, between exiting `from` and entering `to`.
+    #[serde(default)]
+    pub actions: Vec<Action>,
+}
+
+impl Transition {
+    /// The delay, guard and actions as they're written in the editor, e.g.
+    /// `after 0.3s: Jump / emit Jumped`.
+    pub fn label(&self, state_types: &impl StateTypeLookup) -> String {
+        let guard = self.guard.as_ref().map(|guard| guard.display(state_types));
+        let label = match (self.after, guard) {
+            (Some(after), Some(guard)) => format!("after {after}s: {guard}"),
+            (Some(after), None) => format!("after {after}s"),
+            (None, guard) => guard.unwrap_or_default(),
+        };
+        if self.actions.is_empty() {
+            return label;
+        }
+        let actions = Action::display_list(&self.actions, state_types);
+        if label.is_empty() {
+            format!("/ {actions}")
+        } else {
+            format!("{label} / {actions}")
+        }
+    }
+
+    /// This transition with the delay, guard and actions read from a label written the way
+    /// [`Transition::label`] writes them.
+    pub fn with_label(&self, source: &str, state_types: &impl StateTypeLookup) -> Result<Self> {
+        // Neither delays nor guards use `/`, so the first one starts the actions
+        let (source, actions) = source.split_once('/').unwrap_or((source, ""));
+        let source = source.trim();
+        // A variable can be called `after` too, but it's never followed by a number
+        let (after, guard) = match source
+            .strip_prefix("after ")
+            .filter(|rest| rest.trim_start().starts_with(|c: char| c.is_ascii_digit()))
+        {
+            Some(rest) => {
+                let (delay, guard) = rest.split_once(':').unwrap_or((rest, ""));
+                let delay = delay.trim();
+                let seconds = delay
+                    .strip_suffix('s')
+                    .unwrap_or(delay)
+                    .trim()
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|seconds| seconds.is_finite())
+                    .ok_or_else(|| format!("Expected a delay in seconds, found {delay:?}"))?;
+                (Some(seconds), guard.trim())
+            }
+            None => (None, source),
+        };
+        let guard = if guard.is_empty() {
+            None
+        } else {
+            Some(Guard::parse(guard, state_types)?)
+        };
+        Ok(Self {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            guard,
+            after,
+            actions: Action::parse_list(actions, state_types)?,
+        })
+    }
+}
@@ -0,0 +1,908 @@
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+
+use crate::action::Action;
+use crate::document::{Document, DocumentState};
+use crate::{HistoryKind, StateId, StateNodeId, StateType, StateTypeValue, Transition};
+
+/// Executes a [`Document`] without any of the editor around it.
+///
+/// Inputs start out at their type's default value and are only changed through
+/// [`Runtime::set_input`] and the [`Action`]s of states and transitions. Nothing happens until
+/// [`Runtime::step`] is called, and time only passes for timed transitions and timers through
+/// [`Runtime::advance`].
+///
+/// The machine is in one state without children per region it's in, and also in every state
+/// those are nested in, see [`Runtime::active`]. Outside of parallel states that's a single state
+/// without children.
+#[derive(Debug, Clone)]
+pub struct Runtime {
+    document: Document,
+    inputs: HashMap<StateId, StateTypeValue>,
+    /// Every active state, each one after the state it's nested in.
+    active: Vec<StateNodeId>,
+    /// The states that were active inside each state when it was last exited, for history
+    /// pseudo-states to resume.
+    remembered: HashMap<StateNodeId, Vec<StateNodeId>>,
+    /// How many seconds ago each active state was entered.
+    dwell: HashMap<StateNodeId, f32>,
+    /// The float variables counting seconds, see [`Action::StartTimer`].
+    timers: Vec<StateId>,
+    /// Events from entering the states the machine started in, for the first step to return.
+    pending: Vec<RuntimeEvent>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeEvent {
+    Exited(StateNodeId),
+    /// Reported between exiting the transition's source and entering its target, just before its
+    /// own actions run.
+    Took(Transition),
+    Entered(StateNodeId),
+    /// An event named by an [`Action::Emit`].
+    Emitted(String),
+}
+
+impl Runtime {
+    /// Starts in the document's initial state, running the entry actions of everything it
+    /// enters. The enter and emitted events that causes are returned by the first
+    /// [`Runtime::step`].
+    pub fn new(document: Document) -> Result<Self> {
+        let initial = document
+            .initial_state()
+            .ok_or("Document has no initial state")?
+            .id
+            .clone();
+        let mut runtime = Self::starting_in(document, &[initial])?;
+        let mut events = vec![];
+        for id in runtime.active.clone() {
+            runtime.run_entry(&id, &mut events);
+        }
+        runtime.pending = events;
+        Ok(runtime)
+    }
+
+    /// Starts in `states` and everything they're nested in, leaving out the ones that can't be
+    /// active along with an earlier one, see [`Document::exclusive`]. States whose children
+    /// aren't active then start in their initial ones, and parallel states in all their regions.
+    ///
+    /// No entry actions are run, as if the machine had been in those states all along.
+    pub fn starting_in(document: Document, states: &[StateNodeId]) -> Result<Self> {
+        let inputs = document
+            .state_types
+            .iter()
+            .map(|state_type| (state_type.id.clone(), state_type.state_type.default_value()))
+            .collect();
+        let mut runtime = Self {
+            document,
+            inputs,
+            active: vec![],
+            remembered: HashMap::new(),
+            dwell: HashMap::new(),
+            timers: vec![],
+            pending: vec![],
+        };
+        let document = &runtime.document;
+
+        let mut active: Vec<StateNodeId> = vec![];
+        for id in states {
+            let Some(state) = document.state(id) else {
+                continue;
+            };
+            if active.iter().any(|other| document.exclusive(id, other)) {
+                continue;
+            }
+            for state in document.ancestors(id).into_iter().rev().chain([state]) {
+                if !active.contains(&state.id) {
+                    active.push(state.id.clone());
+                }
+            }
+        }
+        if active.is_empty() {
+            return Err(format!("Initial states {states:?} not found").into());
+        }
+
+        // Entered states are appended, so they get filled in as well
+        let mut index = 0;
+        while let Some(id) = active.get(index).cloned() {
+            let mut children = document.children(Some(&id)).collect::<Vec<_>>();
+            children.sort_by_key(|child| !child.initial);
+            let missing = if document.state(&id).is_some_and(|state| state.parallel) {
+                children
+                    .into_iter()
+                    .filter(|child| !active.contains(&child.id))
+                    .collect()
+            } else if children.iter().all(|child| !active.contains(&child.id)) {
+                children.into_iter().take(1).collect()
+            } else {
+                vec![]
+            };
+            for child in missing {
+                let entered = runtime
+                    .enter(&child.id, &[], &[], false)
+                    .unwrap_or_default();
+                active.extend(entered.into_iter().map(|state| state.id.clone()));
+            }
+            index += 1;
+        }
+        runtime.dwell = active.iter().map(|id| (id.clone(), 0.0)).collect();
+        runtime.active = active;
+        Ok(runtime)
+    }
+
+    /// Takes over what `other` remembers for history pseudo-states, how long its states have
+    /// been active and which timers are running, where those states and variables still exist,
+    /// e.g. after restarting on an edited document.
+    pub fn continue_from(&mut self, other: &Runtime) {
+        self.timers = other
+            .timers
+            .iter()
+            .filter(|id| self.is_timer(id))
+            .cloned()
+            .collect();
+        for id in self.active.iter() {
+            if let Some(dwell) = other.dwell.get(id) {
+                self.dwell.insert(id.clone(), *dwell);
+            }
+        }
+        for (id, nested) in other.remembered.iter() {
+            if self.document.state(id).is_some() {
+                let nested = nested
+                    .iter()
+                    .filter(|id| self.document.state(id).is_some())
+                    .cloned()
+                    .collect();
+                self.remembered.insert(id.clone(), nested);
+            }
+        }
+    }
+
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    /// Every state the machine is in, each one after the state it's nested in.
+    pub fn active(&self) -> Vec<&DocumentState> {
+        self.active
+            .iter()
+            .filter_map(|id| self.document.state(id))
+            .collect()
+    }
+
+    /// The innermost states the machine is in, one per region, which have no children.
+    pub fn current(&self) -> Vec<&DocumentState> {
+        self.active()
+            .into_iter()
+            .filter(|state| {
+                self.document
+                    .children(Some(&state.id))
+                    .all(|child| !self.active.contains(&child.id))
+            })
+            .collect()
+    }
+
+    /// Whether `state` or a state it's nested in is terminal, after which nothing happens in its
+    /// region anymore.
+    fn is_stopped(&self, state: &DocumentState) -> bool {
+        state.terminal
+            || self
+                .document
+                .ancestors(&state.id)
+                .iter()
+                .any(|state| state.terminal)
+    }
+
+    /// Whether every region the machine is in reached a terminal state, after which nothing
+    /// happens anymore.
+    pub fn is_finished(&self) -> bool {
+        self.current()
+            .into_iter()
+            .all(|state| self.is_stopped(state))
+    }
+
+    /// Lets `seconds` pass, which can enable timed transitions for the next [`Runtime::step`] and
+    /// counts up running timers.
+    pub fn advance(&mut self, seconds: f32) {
+        let seconds = seconds.max(0.0);
+        for dwell in self.dwell.values_mut() {
+            *dwell += seconds;
+        }
+        for id in self.timers.clone() {
+            if let Some(StateTypeValue::Float(time)) = self.inputs.get(&id) {
+                self.set_coerced(&id, StateTypeValue::Float(time + seconds));
+            }
+        }
+    }
+
+    fn is_timer(&self, id: &StateId) -> bool {
+        self.document
+            .state_types
+            .iter()
+            .any(|data| data.id == *id && matches!(data.state_type, StateType::Float { .. }))
+    }
+
+    /// Sets a variable to the closest value its type accepts, see [`StateType::coerce`].
+    fn set_coerced(&mut self, state_type: &StateId, value: StateTypeValue) {
+        if let Some(data) = self
+            .document
+            .state_types
+            .iter()
+            .find(|data| data.id == *state_type)
+        {
+            let value = data.state_type.coerce(value);
+            self.inputs.insert(state_type.clone(), value);
+        }
+    }
+
+    /// Runs `actions` in order, adding the events they emit to `events`. Actions on variables
+    /// that no longer fit are skipped.
+    fn run(&mut self, actions: &[Action], events: &mut Vec<RuntimeEvent>) {
+        for action in actions {
+            match action {
+                Action::Set { variable, value } => {
+                    let _ = self.set_input(variable, value.clone());
+                }
+                Action::Emit(event) => {
+                    events.push(RuntimeEvent::Emitted(event.clone()));
+                    let trigger = self
+                        .document
+                        .state_types
+                        .iter()
+                        .find(|data| data.name == *event && data.state_type == StateType::Trigger)
+                        .map(|data| data.id.clone());
+                    if let Some(trigger) = trigger {
+                        let _ = self.fire(&trigger);
+                    }
+                }
+                Action::StartTimer(variable) => {
+                    if self.is_timer(variable) {
+                        self.set_coerced(variable, StateTypeValue::Float(0.0));
+                        if !self.timers.contains(variable) {
+                            self.timers.push(variable.clone());
+                        }
+                    }
+                }
+                Action::StopTimer(variable) => self.timers.retain(|timer| timer != variable),
+            }
+        }
+    }
+
+    /// Reports entering `id` and runs its entry actions.
+    fn run_entry(&mut self, id: &StateNodeId, events: &mut Vec<RuntimeEvent>) {
+        let actions = self
+            .document
+            .state(id)
+            .map(|state| state.on_entry.clone())
+            .unwrap_or_default();
+        events.push(RuntimeEvent::Entered(id.clone()));
+        self.run(&actions, events);
+    }
+
+    /// Reports exiting `id` and runs its exit actions.
+    fn run_exit(&mut self, id: &StateNodeId, events: &mut Vec<RuntimeEvent>) {
+        let actions = self
+            .document
+            .state(id)
+            .map(|state| state.on_exit.clone())
+            .unwrap_or_default();
+        events.push(RuntimeEvent::Exited(id.clone()));
+        self.run(&actions, events);
+    }
+
+    /// How many seconds ago `state` was entered, `None` if it isn't active.
+    pub fn time_in(&self, state: &StateNodeId) -> Option<f32> {
+        self.dwell.get(state).copied()
+    }
+
+    /// How far along the delay of `transition` is, from 0 to 1. `None` if it isn't timed or its
+    /// source isn't active.
+    pub fn progress(&self, transition: &Transition) -> Option<f32> {
+        let after = transition.after?;
+        let time = self.time_in(&transition.from)?;
+        Some(if after > 0.0 {
+            (time / after).min(1.0)
+        } else {
+            1.0
+        })
+    }
+
+    pub fn input(&self, state_type: &StateId) -> Option<&StateTypeValue> {
+        self.inputs.get(state_type)
+    }
+
+    pub fn set_input(&mut self, state_type: &StateId, value: StateTypeValue) -> Result {
+        let data = self
+            .document
+            .state_types
+            .iter()
+            .find(|data| data.id == *state_type)
+            .ok_or("StateType not found")?;
+        if !data.state_type.accepts(&value) {
+            return Err(format!("{value:?} is not a valid value for {}", data.name).into());
+        }
+        self.inputs.insert(state_type.clone(), value);
+        Ok(())
+    }
+
+    /// Whether every variable assigned by `state` has the same value in the current inputs.
+    pub fn matches(&self, state: &DocumentState) -> bool {
+        state.state.iter().all(|value| {
+            self.inputs
+                .get(&value.state)
+                .is_some_and(|input| value.matches(input))
+        })
+    }
+
+    /// Enters `id` and what's nested in it, outermost first, or `None` if `check` is set and
+    /// anything entered doesn't match the inputs.
+    ///
+    /// `towards` are the states below `id` to enter down to, outermost first. Below those the
+    /// first child that can be entered is, children in `resume` first and then initial ones, and
+    /// every region of a parallel state.
+    fn enter(
+        &self,
+        id: &StateNodeId,
+        towards: &[StateNodeId],
+        resume: &[StateNodeId],
+        check: bool,
+    ) -> Option<Vec<&DocumentState>> {
+        let state = self.document.state(id)?;
+        if check && !self.matches(state) {
+            return None;
+        }
+        let mut children = self.document.children(Some(id)).collect::<Vec<_>>();
+        children.sort_by_key(|child| (!resume.contains(&child.id), !child.initial));
+        let mut entered = vec![state];
+        if state.parallel {
+            for child in children {
+                let towards = match towards.split_first() {
+                    Some((next, rest)) if *next == child.id => rest,
+                    _ => &[],
+                };
+                entered.extend(self.enter(&child.id, towards, resume, check)?);
+            }
+        } else if let Some((next, rest)) = towards.split_first() {
+            entered.extend(self.enter(next, rest, resume, check)?);
+        } else if !children.is_empty() {
+            entered.extend(
+                children
+                    .iter()
+                    .find_map(|child| self.enter(&child.id, &[], resume, check))?,
+            );
+        }
+        Some(entered)
+    }
+
+    /// Where `transition` goes: its target, or for a history pseudo-state the state that's
+    /// nested in, along with the states to resume in there.
+    fn target(&self, transition: &Transition) -> Option<(StateNodeId, Vec<StateNodeId>)> {
+        let to = self.document.state(&transition.to)?;
+        let Some(kind) = to.history else {
+            return Some((to.id.clone(), vec![]));
+        };
+        let parent = to.parent.as_ref()?;
+        Some((parent.clone(), self.remembered(parent, kind)))
+    }
+
+    /// The states nested in `parent` that a history pseudo-state of `kind` resumes. While
+    /// `parent` is active those are the active ones, since the transition exits it first.
+    fn remembered(&self, parent: &StateNodeId, kind: HistoryKind) -> Vec<StateNodeId> {
+        let nested = if self.active.contains(parent) {
+            self.active
+                .iter()
+                .filter(|id| {
+                    self.document
+                        .ancestors(id)
+                        .iter()
+                        .any(|ancestor| ancestor.id == *parent)
+                })
+                .cloned()
+                .collect()
+        } else {
+            self.remembered.get(parent).cloned().unwrap_or_default()
+        };
+        match kind {
+            HistoryKind::Shallow => nested
+                .into_iter()
+                .filter(|id| {
+                    self.document
+                        .state(id)
+                        .is_some_and(|state| state.parent.as_ref() == Some(parent))
+                })
+                .collect(),
+            HistoryKind::Deep => nested,
+        }
+    }
+
+    /// The states a transition to the history pseudo-state `history` would resume, the
+    /// innermost ones only. Empty if it would enter its parent as usual, because the parent
+    /// wasn't active yet.
+    pub fn resumed(&self, history: &StateNodeId) -> Vec<&DocumentState> {
+        let Some(state) = self.document.state(history) else {
+            return vec![];
+        };
+        let (Some(kind), Some(parent)) = (state.history, &state.parent) else {
+            return vec![];
+        };
+        let remembered = self.remembered(parent, kind);
+        remembered
+            .iter()
+            .filter_map(|id| self.document.state(id))
+            .filter(|state| {
+                self.document
+                    .children(Some(&state.id))
+                    .all(|child| !remembered.contains(&child.id))
+            })
+            .collect()
+    }
+
+    /// The innermost state both `from` and `to` are nested in that isn't parallel, which stays
+    /// active when a transition between them is taken. `None` is the top level.
+    fn domain(&self, from: &StateNodeId, to: &StateNodeId) -> Option<StateNodeId> {
+        let to = self.document.ancestors(to);
+        self.document
+            .ancestors(from)
+            .into_iter()
+            .find(|ancestor| !ancestor.parallel && to.iter().any(|other| other.id == ancestor.id))
+            .map(|ancestor| ancestor.id.clone())
+    }
+
+    /// The states taking `transition` enters, outermost first, or `None` if any of them or the
+    /// states its target is nested in don't match the inputs.
+    fn entry(&self, transition: &Transition) -> Option<Vec<&DocumentState>> {
+        let (target, resume) = self.target(transition)?;
+        let domain = self.domain(&transition.from, &target);
+        let ancestors = self.document.ancestors(&target);
+        let below = ancestors
+            .iter()
+            .take_while(|state| Some(&state.id) != domain.as_ref())
+            .count();
+        if !ancestors.iter().all(|state| self.matches(state)) {
+            return None;
+        }
+        let mut path = ancestors[..below]
+            .iter()
+            .map(|state| state.id.clone())
+            .collect::<Vec<_>>();
+        path.reverse();
+        path.push(target);
+        let (first, rest) = path.split_first()?;
+        self.enter(first, rest, &resume, true)
+    }
+
+    /// The active states taking `transition` exits, innermost first.
+    fn exits(&self, transition: &Transition) -> Vec<&DocumentState> {
+        let domain = self
+            .target(transition)
+            .and_then(|(target, _)| self.domain(&transition.from, &target));
+        let mut exited = self
+            .active()
+            .into_iter()
+            .filter(|state| {
+                domain.as_ref().is_none_or(|domain| {
+                    self.document
+                        .ancestors(&state.id)
+                        .iter()
+                        .any(|ancestor| ancestor.id == *domain)
+                })
+            })
+            .collect::<Vec<_>>();
+        exited.reverse();
+        exited
+    }
+
+    /// The transitions the next [`Runtime::step`] takes, at most one per region.
+    ///
+    /// Each active state without children that isn't stopped by a terminal state picks the
+    /// first transition whose guard holds, whose delay has passed and whose target matches the
+    /// inputs, its own transitions before the ones of the states it's nested in. A transition
+    /// that would exit a state an earlier one already exits is left out.
+    pub fn enabled_transitions(&self) -> Vec<&Transition> {
+        let mut enabled = vec![];
+        let mut exited = vec![];
+        for leaf in self.current() {
+            if self.is_stopped(leaf) {
+                continue;
+            }
+            let transition = [leaf]
+                .into_iter()
+                .chain(self.document.ancestors(&leaf.id))
+                .find_map(|source| {
+                    self.document
+                        .transitions
+                        .iter()
+                        .filter(|transition| transition.from == source.id)
+                        .filter(|transition| {
+                            transition
+                                .guard
+                                .as_ref()
+                                .is_none_or(|guard| guard.evaluate(&self.inputs))
+                        })
+                        .filter(|transition| {
+                            transition.after.is_none_or(|after| {
+                                self.time_in(&transition.from)
+                                    .is_some_and(|time| time >= after)
+                            })
+                        })
+                        .find(|transition| self.entry(transition).is_some())
+                });
+            let Some(transition) = transition else {
+                continue;
+            };
+            let exits = self.exits(transition);
+            if exits.iter().any(|state| exited.contains(&&state.id)) {
+                continue;
+            }
+            exited.extend(exits.into_iter().map(|state| &state.id));
+            enabled.push(transition);
+        }
+        enabled
+    }
+
+    /// Sets a trigger for the next [`Runtime::step`].
+    pub fn fire(&mut self, trigger: &StateId) -> Result {
+        self.set_input(trigger, StateTypeValue::Trigger(true))
+    }
+
+    /// Takes the [`Runtime::enabled_transitions`], returning the exit, take, enter and emitted
+    /// events they caused, after any left over from starting. Triggers are cleared before any
+    /// actions run, whether or not they were used, so triggers the actions fire are there for the
+    /// next step.
+    ///
+    /// For each transition everything inside the innermost non-parallel state containing both
+    /// ends is exited, innermost first, and everything down to the target is entered, outermost
+    /// first, along with the other regions of parallel states on the way. A transition to a
+    /// history pseudo-state goes to the state it's in and resumes what was active in there, see
+    /// [`HistoryKind`]. Each state's exit actions run as it's exited, then the transition's own
+    /// actions, and then each state's entry actions as it's entered.
+    pub fn step(&mut self) -> Vec<RuntimeEvent> {
+        self.take(false)
+    }
+
+    /// Like [`Runtime::step`], but only takes the enabled transitions with a delay, leaving the
+    /// rest and the triggers for the next step. Lets a timed transition be taken as soon as its
+    /// delay has passed rather than on the next beat.
+    pub fn step_timed(&mut self) -> Vec<RuntimeEvent> {
+        self.take(true)
+    }
+
+    fn take(&mut self, timed_only: bool) -> Vec<RuntimeEvent> {
+        let taken = self
+            .enabled_transitions()
+            .into_iter()
+            .filter(|transition| !timed_only || transition.after.is_some())
+            .filter_map(|transition| {
+                let entered = self.entry(transition)?;
+                let exited = self.exits(transition);
+                Some((
+                    transition.clone(),
+                    exited
+                        .into_iter()
+                        .map(|state| state.id.clone())
+                        .collect::<Vec<_>>(),
+                    entered
+                        .into_iter()
+                        .map(|state| state.id.clone())
+                        .collect::<Vec<_>>(),
+                ))
+            })
+            .collect::<Vec<_>>();
+        if !timed_only {
+            for value in self.inputs.values_mut() {
+                if let StateTypeValue::Trigger(fired) = value {
+                    *fired = false;
+                }
+            }
+        }
+        let mut events = std::mem::take(&mut self.pending);
+        for (transition, exited, entered) in taken {
+            for id in exited.iter() {
+                let nested = self
+                    .active
+                    .iter()
+                    .filter(|other| {
+                        self.document
+                            .ancestors(other)
+                            .iter()
+                            .any(|ancestor| ancestor.id == *id)
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                if !nested.is_empty() {
+                    self.remembered.insert(id.clone(), nested);
+                }
+            }
+            self.active.retain(|id| !exited.contains(id));
+            self.active.extend(entered.iter().cloned());
+            for id in exited.iter() {
+                self.dwell.remove(id);
+            }
+            for id in entered.iter() {
+                self.dwell.insert(id.clone(), 0.0);
+            }
+            for id in exited.iter() {
+                self.run_exit(id, &mut events);
+            }
+            let actions = transition.actions.clone();
+            events.push(RuntimeEvent::Took(transition));
+            self.run(&actions, &mut events);
+            for id in entered.iter() {
+                self.run_entry(id, &mut events);
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StateTypeData;
+    use crate::document::fixtures::{assigning, document, guarded, state, transition};
+
+    fn current(runtime: &Runtime) -> Vec<&str> {
+        runtime
+            .current()
+            .into_iter()
+            .map(|state| state.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn exits_are_reported_before_entries() {
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let start = state("Start", None, true);
+        let end = state("End", None, false);
+        let taken = guarded(&start, &end, "Go", &vec![go.clone()]);
+        let document = document(vec![go.clone()], &[&start, &end], vec![taken.clone()]);
+        let mut runtime = Runtime::new(document).unwrap();
+        runtime.step();
+        assert_eq!(current(&runtime), ["Start"]);
+        runtime.fire(&go.id).unwrap();
+        assert_eq!(
+            runtime.step(),
+            vec![
+                RuntimeEvent::Exited(start.id.clone()),
+                RuntimeEvent::Took(taken),
+                RuntimeEvent::Entered(end.id.clone()),
+            ]
+        );
+        assert_eq!(current(&runtime), ["End"]);
+        assert_eq!(runtime.input(&go.id), Some(&StateTypeValue::Trigger(false)));
+    }
+
+    #[test]
+    fn targets_have_to_match_the_inputs() {
+        let speed = StateTypeData::new("Speed", StateType::Int);
+        let start = state("Start", None, true);
+        let slow = assigning("Slow", &speed, StateTypeValue::Int(1));
+        let fast = assigning("Fast", &speed, StateTypeValue::Int(2));
+        let document = document(
+            vec![speed.clone()],
+            &[&start, &slow, &fast],
+            vec![transition(&start, &slow), transition(&start, &fast)],
+        );
+        for (value, expected) in [(1, "Slow"), (2, "Fast"), (3, "Start")] {
+            let mut runtime = Runtime::new(document.clone()).unwrap();
+            runtime
+                .set_input(&speed.id, StateTypeValue::Int(value))
+                .unwrap();
+            runtime.step();
+            assert_eq!(current(&runtime), [expected]);
+        }
+    }
+
+    #[test]
+    fn any_matches_every_value() {
+        let speed = StateTypeData::new("Speed", StateType::Int);
+        let start = state("Start", None, true);
+        let exact = assigning("Exact", &speed, StateTypeValue::Int(2));
+        let mut anything = assigning("Anything", &speed, StateTypeValue::Int(2));
+        anything.state[0].any = true;
+        let document = document(
+            vec![speed.clone()],
+            &[&start, &exact, &anything],
+            vec![transition(&start, &exact), transition(&start, &anything)],
+        );
+        for (value, expected) in [(2, "Exact"), (3, "Anything")] {
+            let mut runtime = Runtime::new(document.clone()).unwrap();
+            runtime
+                .set_input(&speed.id, StateTypeValue::Int(value))
+                .unwrap();
+            runtime.step();
+            assert_eq!(current(&runtime), [expected]);
+        }
+    }
+
+    #[test]
+    fn nested_states_inherit_transitions_after_their_own() {
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let parent = state("Parent", None, true);
+        let first = state("First", Some(&parent), true);
+        let second = state("Second", Some(&parent), false);
+        let out = state("Out", None, false);
+        let own = guarded(&first, &second, "Go", &vec![go.clone()]);
+        let document = document(
+            vec![go.clone()],
+            &[&parent, &first, &second, &out],
+            vec![transition(&parent, &out), own],
+        );
+        let mut runtime = Runtime::new(document).unwrap();
+        runtime.fire(&go.id).unwrap();
+        runtime.step();
+        assert_eq!(current(&runtime), ["Second"]);
+        runtime.step();
+        assert_eq!(current(&runtime), ["Out"]);
+    }
+
+    #[test]
+    fn transition_only_exits_below_the_common_ancestor() {
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let outer = state("Outer", None, true);
+        let left = state("Left", Some(&outer), true);
+        let left_inner = state("Left inner", Some(&left), true);
+        let right = state("Right", Some(&outer), false);
+        let right_inner = state("Right inner", Some(&right), true);
+        let taken = guarded(&left_inner, &right_inner, "Go", &vec![go.clone()]);
+        let document = document(
+            vec![go.clone()],
+            &[&outer, &left, &left_inner, &right, &right_inner],
+            vec![taken.clone()],
+        );
+        let mut runtime = Runtime::new(document).unwrap();
+        runtime.step();
+        runtime.fire(&go.id).unwrap();
+        assert_eq!(
+            runtime.step(),
+            vec![
+                RuntimeEvent::Exited(left_inner.id.clone()),
+                RuntimeEvent::Exited(left.id.clone()),
+                RuntimeEvent::Took(taken),
+                RuntimeEvent::Entered(right.id.clone()),
+                RuntimeEvent::Entered(right_inner.id.clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn history_resumes_the_child_or_everything_nested() {
+        let state_types = vec![
+            StateTypeData::new("Go", StateType::Trigger),
+            StateTypeData::new("Out", StateType::Trigger),
+            StateTypeData::new("Back", StateType::Trigger),
+        ];
+        let [go, out, back] = [0, 1, 2].map(|index| state_types[index].id.clone());
+        let parent = state("Parent", None, true);
+        let first = state("First", Some(&parent), true);
+        let second = state("Second", Some(&parent), false);
+        let second_first = state("Second first", Some(&second), true);
+        let second_next = state("Second next", Some(&second), false);
+        let mut shallow = state("Shallow", Some(&parent), false);
+        shallow.history = Some(HistoryKind::Shallow);
+        let mut deep = state("Deep", Some(&parent), false);
+        deep.history = Some(HistoryKind::Deep);
+        let away = state("Away", None, false);
+        let transitions = vec![
+            guarded(&first, &second, "Go", &state_types),
+            guarded(&second_first, &second_next, "Go", &state_types),
+            guarded(&parent, &away, "Out", &state_types),
+            guarded(&away, &shallow, "Back", &state_types),
+            guarded(&away, &deep, "Go", &state_types),
+        ];
+        let document = document(
+            state_types,
+            &[
+                &parent,
+                &first,
+                &second,
+                &second_first,
+                &second_next,
+                &shallow,
+                &deep,
+                &away,
+            ],
+            transitions,
+        );
+        let mut runtime = Runtime::new(document).unwrap();
+        for trigger in [&go, &go, &out] {
+            runtime.fire(trigger).unwrap();
+            runtime.step();
+        }
+        assert_eq!(current(&runtime), ["Away"]);
+
+        let mut resumed_shallow = runtime.clone();
+        resumed_shallow.fire(&back).unwrap();
+        resumed_shallow.step();
+        assert_eq!(current(&resumed_shallow), ["Second first"]);
+
+        runtime.fire(&go).unwrap();
+        runtime.step();
+        assert_eq!(current(&runtime), ["Second next"]);
+    }
+
+    #[test]
+    fn timed_transition_waits_for_its_delay() {
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let start = state("Start", None, true);
+        let later = state("Later", None, false);
+        let other = state("Other", None, false);
+        let mut timed = transition(&start, &later);
+        timed.after = Some(1.0);
+        let document = document(
+            vec![go.clone()],
+            &[&start, &later, &other],
+            vec![
+                guarded(&start, &other, "Go", &vec![go.clone()]),
+                timed.clone(),
+            ],
+        );
+        let mut runtime = Runtime::new(document).unwrap();
+        runtime.advance(0.75);
+        runtime.step();
+        assert_eq!(current(&runtime), ["Start"]);
+        assert_eq!(runtime.progress(&timed), Some(0.75));
+
+        runtime.advance(0.25);
+        assert!(runtime.step_timed().contains(&RuntimeEvent::Took(timed)));
+        assert_eq!(current(&runtime), ["Later"]);
+        assert_eq!(runtime.time_in(&later.id), Some(0.0));
+    }
+
+    #[test]
+    fn timed_steps_leave_untimed_transitions_and_triggers() {
+        let go = StateTypeData::new("Go", StateType::Trigger);
+        let start = state("Start", None, true);
+        let other = state("Other", None, false);
+        let document = document(
+            vec![go.clone()],
+            &[&start, &other],
+            vec![guarded(&start, &other, "Go", &vec![go.clone()])],
+        );
+        let mut runtime = Runtime::new(document).unwrap();
+        runtime.fire(&go.id).unwrap();
+        runtime.step_timed();
+        assert_eq!(current(&runtime), ["Start"]);
+        assert_eq!(runtime.input(&go.id), Some(&StateTypeValue::Trigger(true)));
+        runtime.step();
+        assert_eq!(current(&runtime), ["Other"]);
+    }
+
+    #[test]
+    fn first_step_reports_entering_the_starting_states() {
+        let mut parent = state("Parent", None, true);
+        parent.on_entry.push(Action::Emit("Started".into()));
+        let inner = state("Inner", Some(&parent), true);
+        let document = document(vec![], &[&parent, &inner], vec![]);
+        let mut runtime = Runtime::new(document).unwrap();
+        assert_eq!(
+            runtime.step(),
+            vec![
+                RuntimeEvent::Entered(parent.id.clone()),
+                RuntimeEvent::Emitted("Started".into()),
+                RuntimeEvent::Entered(inner.id.clone()),
+            ]
+        );
+        assert!(runtime.step().is_empty());
+    }
+
+    #[test]
+    fn actions_run_on_exit_then_transition_then_entry() {
+        let mut start = state("Start", None, true);
+        start.on_exit.push(Action::Emit("Left".into()));
+        let mut end = state("End", None, false);
+        end.on_entry.push(Action::Emit("Arrived".into()));
+        let mut taken = transition(&start, &end);
+        taken.actions.push(Action::Emit("Moving".into()));
+        let document = document(vec![], &[&start, &end], vec![taken]);
+        let mut runtime = Runtime::new(document).unwrap();
+        let emitted = runtime
+            .step()
+            .into_iter()
+            .filter_map(|event| match event {
+                RuntimeEvent::Emitted(name) => Some(name),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(emitted, ["Left", "Moving", "Arrived"]);
+    }
+}
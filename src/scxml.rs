@@ -0,0 +1,1346 @@
+//! W3C SCXML import and export, for exchanging machines with other tools.
+//!
+//! SCXML states don't assign values, so on export the values a target state assigns become part
+//! of the `cond` of every transition into it, and imported states don't assign anything.
+//! Triggers map to SCXML events. Actions become `<assign>`s and `<raise>`s, and only those come
+//! back on import. Anything that has no equivalent on the other side is listed in `unmapped`
+//! rather than silently dropped.
+
+use std::fmt::Write;
+
+// Shadowed by the SCXML `Condition` below, but needed to combine run conditions
+use bevy::ecs::schedule::Condition as _;
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+
+use crate::action::Action;
+use crate::bindings::no_bound_key_pressed;
+use crate::document::{
+    CurrentDocument, DOCUMENT_VERSION, Document, DocumentPath, DocumentState, OpenDocument,
+};
+use crate::guard::{CompareOp, Guard};
+use crate::text_input::no_text_input_active;
+use crate::{
+    HistoryKind, StateId, StateNodeId, StateType, StateTypeData, StateTypeLookup, StateTypeValue,
+    Transition,
+};
+
+pub struct ScxmlPlugin;
+
+impl Plugin for ScxmlPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            scxml_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+        )
+        .add_observer(import_scxml_file)
+        .add_observer(export_scxml_file);
+    }
+}
+
+/// Replaces the editor's contents with the SCXML file next to the [`DocumentPath`].
+#[derive(Event, Debug, Default)]
+pub struct ImportScxml;
+
+/// Writes the editor's contents next to the [`DocumentPath`], with an `scxml` extension.
+#[derive(Event, Debug, Default)]
+pub struct ExportScxml;
+
+fn scxml_shortcuts(keys: Res<ButtonInput<KeyCode>>, mut commands: Commands) {
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+        || !keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+    {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyS) {
+        commands.trigger(ExportScxml);
+    }
+    if keys.just_pressed(KeyCode::KeyO) {
+        commands.trigger(ImportScxml);
+    }
+}
+
+/// Keeps the current document open if the file is missing or can't be read as SCXML.
+fn import_scxml_file(
+    _trigger: Trigger<ImportScxml>,
+    path: Res<DocumentPath>,
+    mut commands: Commands,
+) {
+    let path = path.with_extension("scxml");
+    let import = std::fs::read_to_string(&path)
+        .map_err(BevyError::from)
+        .and_then(|source| import_scxml(&source));
+    let import = match import {
+        Ok(import) => import,
+        Err(error) => {
+            warn!("Couldn't import {}: {error}", path.display());
+            return;
+        }
+    };
+    for unmapped in import.unmapped.iter() {
+        warn!("Not imported from {}: {unmapped}", path.display());
+    }
+    commands.queue(OpenDocument(import.document));
+    info!("Imported {}", path.display());
+}
+
+fn export_scxml_file(
+    _trigger: Trigger<ExportScxml>,
+    path: Res<DocumentPath>,
+    current: CurrentDocument,
+) {
+    let path = path.with_extension("scxml");
+    let export = match export_scxml(&current.document()) {
+        Ok(export) => export,
+        Err(error) => {
+            warn!("Couldn't export {}: {error}", path.display());
+            return;
+        }
+    };
+    for unmapped in export.unmapped.iter() {
+        warn!("Not exported to {}: {unmapped}", path.display());
+    }
+    match std::fs::write(&path, export.scxml) {
+        Ok(()) => info!("Exported {}", path.display()),
+        Err(error) => warn!("Couldn't export {}: {error}", path.display()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScxmlImport {
+    pub document: Document,
+    /// Everything in the SCXML that has no equivalent here, one sentence each.
+    pub unmapped: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScxmlExport {
+    pub scxml: String,
+    /// Everything in the document that has no equivalent in SCXML, one sentence each.
+    pub unmapped: Vec<String>,
+}
+
+/// Reads the `ecmascript` subset that maps onto [`Guard`]s: comparisons, `&&`, `||`, `!` and
+/// `_event.name == '...'` for events.
+pub fn import_scxml(source: &str) -> Result<ScxmlImport> {
+    let xml = roxmltree::Document::parse(source)?;
+    let root = xml.root_element();
+    if root.tag_name().name() != "scxml" {
+        return Err(format!("Expected <scxml>, found <{}>", root.tag_name().name()).into());
+    }
+    let mut unmapped = vec![];
+
+    let mut states = vec![];
+    collect_states(root, None, &mut states, &mut unmapped);
+    let ids = states
+        .iter()
+        .enumerate()
+        .map(|(index, (state, _))| {
+            state
+                .attribute("id")
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("State {}", index + 1))
+        })
+        .collect::<Vec<_>>();
+    let siblings = |parent: Option<usize>| {
+        states
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, other))| *other == parent)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>()
+    };
+    // Without an initial attribute or element SCXML starts in the first child
+    let initial = states
+        .iter()
+        .map(|(_, parent)| {
+            let siblings = siblings(*parent);
+            let node = parent.map_or(root, |parent| states[parent].0);
+            initial_target(node)
+                .and_then(|initial| {
+                    siblings
+                        .iter()
+                        .copied()
+                        .find(|&index| ids[index] == initial)
+                })
+                .or_else(|| {
+                    siblings
+                        .iter()
+                        .copied()
+                        .find(|&index| !states[index].0.has_tag_name("history"))
+                })
+        })
+        .collect::<Vec<_>>();
+
+    // Conditions are read twice: once to find out the types of the data they compare, then for
+    // real once all state types exist
+    let mut conditions = vec![];
+    let mut events = vec![];
+    for index in 0..states.len() {
+        if states[index].0.has_tag_name("history") {
+            // A default to where the parent starts anyway is what the history state does already
+            let default = states[index]
+                .0
+                .children()
+                .find(|child| child.has_tag_name("transition"))
+                .and_then(|transition| transition.attribute("target"));
+            let initial = initial[index].map(|initial| ids[initial].as_str());
+            if default.is_some() && default != initial {
+                unmapped.push(format!(
+                    "Default of history state {}, its parent starts as usual until it's been left",
+                    ids[index]
+                ));
+            }
+            continue;
+        }
+        for transition in states[index]
+            .0
+            .children()
+            .filter(|child| child.has_tag_name("transition"))
+        {
+            let cond = transition
+                .attribute("cond")
+                .map(|cond| translate_condition(cond).map_err(|error| error.to_string()));
+            if let Some(Ok(cond)) = &cond {
+                events.extend(cond.events.iter().cloned());
+            }
+            let transition_events = transition
+                .attribute("event")
+                .map(|event| {
+                    event
+                        .split_whitespace()
+                        .map(|event| {
+                            if event.contains('*') {
+                                unmapped.push(format!(
+                                    "Event wildcard {event} in {} is read as an event name",
+                                    ids[index]
+                                ));
+                            }
+                            event
+                                .trim_end_matches('*')
+                                .trim_end_matches('.')
+                                .to_string()
+                        })
+                        .filter(|event| !event.is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            events.extend(transition_events.iter().cloned());
+            conditions.push((index, transition, transition_events, cond));
+        }
+    }
+
+    let parsed = conditions
+        .iter()
+        .filter_map(|(.., cond)| cond.as_ref()?.as_ref().ok())
+        .collect::<Vec<_>>();
+    let state_types = data_model(root, &parsed, &events, &mut unmapped);
+
+    let mut ids_by_index = HashMap::new();
+    let mut document_states = vec![];
+    for (index, (state, parent)) in states.iter().enumerate() {
+        let id: StateNodeId = rand::random();
+        ids_by_index.insert(index, id.clone());
+        // Laid out in a grid per parent, since only one parent's children are shown at a time
+        let position = siblings(*parent)
+            .iter()
+            .position(|&sibling| sibling == index)
+            .unwrap_or_default();
+        document_states.push(DocumentState {
+            id,
+            name: ids[index].clone(),
+            position: Vec2::new(
+                50.0 + (position % 4) as f32 * 250.0,
+                50.0 + (position / 4) as f32 * 200.0,
+            ),
+            state: vec![],
+            // Parents come before their children
+            parent: parent.map(|parent| ids_by_index[&parent].clone()),
+            parallel: state.has_tag_name("parallel"),
+            history: state
+                .has_tag_name("history")
+                .then(|| match state.attribute("type") {
+                    Some("deep") => HistoryKind::Deep,
+                    _ => HistoryKind::Shallow,
+                }),
+            initial: initial[index] == Some(index),
+            terminal: state.has_tag_name("final"),
+            on_entry: state
+                .children()
+                .filter(|child| child.has_tag_name("onentry"))
+                .flat_map(|content| {
+                    let context = format!("the entry actions of {}", ids[index]);
+                    import_actions(content, &state_types, &context, &mut unmapped)
+                })
+                .collect(),
+            on_exit: state
+                .children()
+                .filter(|child| child.has_tag_name("onexit"))
+                .flat_map(|content| {
+                    let context = format!("the exit actions of {}", ids[index]);
+                    import_actions(content, &state_types, &context, &mut unmapped)
+                })
+                .collect(),
+        });
+    }
+    let state_id = |name: &str| {
+        document_states
+            .iter()
+            .find(|state| state.name == name)
+            .map(|state| state.id.clone())
+    };
+
+    let mut transitions = vec![];
+    for (index, transition, transition_events, cond) in conditions {
+        let from = &ids[index];
+        let Some(targets) = transition.attribute("target") else {
+            unmapped.push(format!("Targetless transition in {from}"));
+            continue;
+        };
+        let mut targets = targets.split_whitespace();
+        let target = targets.next().unwrap_or_default();
+        if targets.next().is_some() {
+            unmapped.push(format!(
+                "Transition from {from} has several targets, only {target} is kept"
+            ));
+        }
+        let Some(to) = state_id(target) else {
+            unmapped.push(format!(
+                "Transition from {from} to {target}, which wasn't imported"
+            ));
+            continue;
+        };
+        let context = format!("the transition from {from} to {target}");
+        let actions = import_actions(transition, &state_types, &context, &mut unmapped);
+
+        let event_guard = transition_events
+            .iter()
+            .filter_map(|event| state_types.state_type_named(event))
+            .map(|data| Guard::Variable(data.id.clone()))
+            .reduce(|a, b| Guard::Or(Box::new(a), Box::new(b)));
+        let cond_guard = cond.map(|cond| {
+            cond.and_then(|cond| {
+                Guard::parse(&cond.source, &state_types).map_err(|error| error.to_string())
+            })
+            .unwrap_or_else(|error| {
+                unmapped.push(format!(
+                    "Condition {:?} from {from} to {target}, the transition is never taken: \
+                     {error}",
+                    transition.attribute("cond").unwrap_or_default()
+                ));
+                Guard::Literal(false)
+            })
+        });
+        let guard = match (event_guard, cond_guard) {
+            (Some(a), Some(b)) => Some(Guard::And(Box::new(a), Box::new(b))),
+            (a, b) => a.or(b),
+        };
+        transitions.push(Transition {
+            from: state_id(from).ok_or("State not found")?,
+            to,
+            guard,
+            after: None,
+            actions,
+        });
+    }
+
+    Ok(ScxmlImport {
+        document: Document {
+            version: DOCUMENT_VERSION,
+            state_types,
+            states: document_states,
+            transitions,
+        },
+        unmapped,
+    })
+}
+
+/// The `<raise>`s, and the `<assign>`s of literals to known data, in the executable content in
+/// `node`, noting the rest as unmapped in `context`.
+fn import_actions(
+    node: roxmltree::Node,
+    state_types: &[StateTypeData],
+    context: &str,
+    unmapped: &mut Vec<String>,
+) -> Vec<Action> {
+    let mut actions = vec![];
+    for child in node.children().filter(|child| child.is_element()) {
+        match child.tag_name().name() {
+            "raise" => match child.attribute("event") {
+                Some(event) => actions.push(Action::Emit(event.to_string())),
+                None => unmapped.push(format!("<raise> without an event in {context}")),
+            },
+            "assign" => {
+                let location = child.attribute("location").unwrap_or_default();
+                let expr = child.attribute("expr").unwrap_or_default();
+                let literal = tokenize_js(expr)
+                    .ok()
+                    .filter(|tokens| tokens.len() == 1)
+                    .and_then(|tokens| match &tokens[0] {
+                        Js::Identifier(text) | Js::String(text) | Js::Number(text) => {
+                            Some(text.clone())
+                        }
+                        Js::Punct(_) => None,
+                    });
+                let action = state_types
+                    .iter()
+                    .find(|data| data.name == location)
+                    .zip(literal)
+                    .and_then(|(data, literal)| {
+                        Some(Action::Set {
+                            variable: data.id.clone(),
+                            value: data.state_type.parse_value(&literal)?,
+                        })
+                    });
+                match action {
+                    Some(action) => actions.push(action),
+                    None => unmapped.push(format!("Assignment {location} = {expr} in {context}")),
+                }
+            }
+            name => unmapped.push(format!("<{name}> in {context}")),
+        }
+    }
+    actions
+}
+
+/// The id of the child SCXML starts `node` in, if it says.
+fn initial_target<'a>(node: roxmltree::Node<'a, '_>) -> Option<&'a str> {
+    node.attribute("initial")
+        .or_else(|| {
+            node.children()
+                .find(|child| child.has_tag_name("initial"))?
+                .children()
+                .find(|child| child.has_tag_name("transition"))?
+                .attribute("target")
+        })
+        .and_then(|targets| targets.split_whitespace().next())
+}
+
+/// Collects every `<state>`, `<parallel>`, `<final>` and `<history>` below `parent`, each with the
+/// index of the state it's nested in, noting what's skipped.
+fn collect_states<'a, 'input>(
+    parent: roxmltree::Node<'a, 'input>,
+    parent_index: Option<usize>,
+    states: &mut Vec<(roxmltree::Node<'a, 'input>, Option<usize>)>,
+    unmapped: &mut Vec<String>,
+) {
+    let parent_name = parent.attribute("id").unwrap_or("the root");
+    for child in parent.children().filter(|child| child.is_element()) {
+        match child.tag_name().name() {
+            "state" | "parallel" | "final" | "history" => {
+                states.push((child, parent_index));
+                collect_states(child, Some(states.len() - 1), states, unmapped);
+            }
+            "script" => unmapped.push(format!("Script in {parent_name} is skipped")),
+            // Read along with the state, once the data model is known
+            "onentry" | "onexit" if parent_index.is_some() => {}
+            "onentry" | "onexit" | "invoke" | "donedata" => unmapped.push(format!(
+                "<{}> in {parent_name} is skipped",
+                child.tag_name().name()
+            )),
+            "transition" | "initial" | "datamodel" => {}
+            name => unmapped.push(format!("Unknown <{name}> in {parent_name} is skipped")),
+        }
+    }
+}
+
+/// A condition translated into [`Guard`] syntax, along with what it reveals about the data.
+struct Condition {
+    source: String,
+    events: Vec<String>,
+    /// Data ids and the literals they're compared to.
+    compared: Vec<(String, Js)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Js {
+    Identifier(String),
+    String(String),
+    Number(String),
+    Punct(&'static str),
+}
+
+fn tokenize_js(source: &str) -> Result<Vec<Js>> {
+    const PUNCTUATION: [&str; 13] = [
+        "===", "!==", "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")",
+    ];
+    let mut tokens = vec![];
+    let mut rest = source.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let (token, length) = if let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(*p))
+        {
+            (Js::Punct(punct), punct.len())
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..].find(c).ok_or("Unterminated string")?;
+            let string = &rest[1..end + 1];
+            if string.contains(['"', '\\']) {
+                return Err(format!("Can't read the string {string:?}").into());
+            }
+            (Js::String(string.to_string()), end + 2)
+        } else if c.is_ascii_digit() || c == '-' || c == '.' {
+            let length = rest[1..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map_or(rest.len(), |end| end + 1);
+            (Js::Number(rest[..length].to_string()), length)
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let length = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '$' && c != '.')
+                .unwrap_or(rest.len());
+            (Js::Identifier(rest[..length].to_string()), length)
+        } else {
+            return Err(format!("Can't read {c:?}").into());
+        };
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+    Ok(tokens)
+}
+
+fn translate_condition(source: &str) -> Result<Condition> {
+    let tokens = tokenize_js(source)?;
+    let mut condition = Condition {
+        source: String::new(),
+        events: vec![],
+        compared: vec![],
+    };
+    let is_comparison = |token: &Js| {
+        matches!(
+            token,
+            Js::Punct("===" | "!==" | "==" | "!=" | "<=" | ">=" | "<" | ">")
+        )
+    };
+    let mut index = 0;
+    while index < tokens.len() {
+        let window = &tokens[index..tokens.len().min(index + 3)];
+        let text = match window {
+            [Js::Identifier(event), Js::Punct(op), Js::String(name)] if event == "_event.name" => {
+                condition.events.push(name.clone());
+                index += 3;
+                if matches!(*op, "==" | "===") {
+                    format!("\"{name}\" ")
+                } else {
+                    format!("(not \"{name}\") ")
+                }
+            }
+            [
+                Js::Identifier(variable),
+                op,
+                literal @ (Js::String(_) | Js::Number(_) | Js::Identifier(_)),
+            ]
+            | [
+                literal @ (Js::String(_) | Js::Number(_)),
+                op,
+                Js::Identifier(variable),
+            ] if is_comparison(op) => {
+                condition.compared.push((variable.clone(), literal.clone()));
+                index += 1;
+                js_to_guard(&tokens[index - 1])
+            }
+            _ => {
+                index += 1;
+                js_to_guard(&tokens[index - 1])
+            }
+        };
+        condition.source.push_str(&text);
+    }
+    Ok(condition)
+}
+
+fn js_to_guard(token: &Js) -> String {
+    match token {
+        Js::Identifier(word) if word == "true" || word == "false" => format!("{word} "),
+        Js::Identifier(name) | Js::String(name) => format!("\"{name}\" "),
+        Js::Number(number) => format!("{number} "),
+        Js::Punct("===") => "== ".to_string(),
+        Js::Punct("!==") => "!= ".to_string(),
+        Js::Punct(punct) => format!("{punct} "),
+    }
+}
+
+/// A state type for every `<data>`, typed by its initial value and what it's compared to, and a
+/// trigger for every event.
+fn data_model(
+    root: roxmltree::Node,
+    conditions: &[&Condition],
+    events: &[String],
+    unmapped: &mut Vec<String>,
+) -> Vec<StateTypeData> {
+    let compared = conditions
+        .iter()
+        .flat_map(|cond| cond.compared.iter())
+        .collect::<Vec<_>>();
+
+    let mut state_types = vec![];
+    for data in root.descendants().filter(|node| node.has_tag_name("data")) {
+        let Some(id) = data.attribute("id") else {
+            unmapped.push("<data> without an id".to_string());
+            continue;
+        };
+        if data.attribute("src").is_some()
+            || data.text().is_some_and(|text| !text.trim().is_empty())
+        {
+            unmapped.push(format!(
+                "Value of {id} is loaded or written inline, which isn't supported"
+            ));
+        }
+        let initial = data
+            .attribute("expr")
+            .and_then(|expr| tokenize_js(expr).ok())
+            .and_then(|tokens| (tokens.len() == 1).then(|| tokens[0].clone()));
+        let literals = initial
+            .iter()
+            .chain(
+                compared
+                    .iter()
+                    .filter(|(variable, _)| variable == id)
+                    .map(|(_, literal)| literal),
+            )
+            .collect::<Vec<_>>();
+
+        let variants = literals
+            .iter()
+            .filter_map(|literal| match literal {
+                Js::String(variant) => Some(variant.clone()),
+                _ => None,
+            })
+            .fold(vec![], |mut variants, variant| {
+                if !variants.contains(&variant) {
+                    variants.push(variant);
+                }
+                variants
+            });
+        let numbers = literals
+            .iter()
+            .filter_map(|literal| match literal {
+                Js::Number(number) => Some(number),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let state_type = if !variants.is_empty() {
+            StateType::Enum(variants)
+        } else if numbers.iter().any(|number| number.parse::<i64>().is_err()) {
+            StateType::Float {
+                min: None,
+                max: None,
+            }
+        } else if !numbers.is_empty() {
+            StateType::Int
+        } else {
+            StateType::Bool
+        };
+
+        // Variables always start at their type's default, which for enums is the first variant
+        let default = state_type.default_value();
+        if let Some(initial) = &initial {
+            let text = match initial {
+                Js::Identifier(text) | Js::String(text) | Js::Number(text) => text.as_str(),
+                Js::Punct(text) => text,
+            };
+            if state_type.parse_value(text) != Some(default.clone()) {
+                unmapped.push(format!(
+                    "Initial value {text} of {id}, it starts at {default}"
+                ));
+            }
+        }
+        state_types.push(StateTypeData::new(id, state_type));
+    }
+
+    let mut seen = HashSet::new();
+    for event in events.iter() {
+        if !seen.insert(event) {
+            continue;
+        }
+        if state_types.state_type_named(event).is_some() {
+            unmapped.push(format!("Event {event} has the same name as data"));
+            continue;
+        }
+        state_types.push(StateTypeData::new(event, StateType::Trigger));
+    }
+    state_types
+}
+
+/// Writes the document as SCXML with the `ecmascript` data model.
+pub fn export_scxml(document: &Document) -> Result<ScxmlExport> {
+    let names = Names::new(document);
+    let mut unmapped = vec![];
+    for data in document.state_types.iter() {
+        match &data.state_type {
+            StateType::Float { min, max } if min.is_some() || max.is_some() => {
+                unmapped.push(format!("Range of {}", data.name));
+            }
+            StateType::Enum(variants) if variants.len() > 1 => {
+                unmapped.push(format!(
+                    "Variants of {}, only those in conditions come back on import",
+                    data.name
+                ));
+            }
+            _ => {}
+        }
+    }
+    for transition in document.transitions.iter() {
+        if transition.after.is_some() {
+            let name = |id| document.state(id).map(|state| state.name.as_str());
+            unmapped.push(format!(
+                "Delay of the transition from {} to {}",
+                name(&transition.from).unwrap_or_default(),
+                name(&transition.to).unwrap_or_default()
+            ));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    write!(
+        out,
+        r#"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" datamodel="ecmascript""#
+    )?;
+    if let Some(initial) = document.initial_state() {
+        write!(
+            out,
+            r#" initial="{}""#,
+            xml_escape(&names.states[&initial.id])
+        )?;
+    }
+    writeln!(out, ">")?;
+
+    let data = document
+        .state_types
+        .iter()
+        .filter(|data| data.state_type != StateType::Trigger)
+        .collect::<Vec<_>>();
+    if !data.is_empty() {
+        writeln!(out, "  <datamodel>")?;
+        for data in data {
+            writeln!(
+                out,
+                r#"    <data id="{}" expr="{}"/>"#,
+                xml_escape(&names.variables[&data.id]),
+                xml_escape(&js_value(&data.state_type.default_value()))
+            )?;
+        }
+        writeln!(out, "  </datamodel>")?;
+    }
+
+    for state in document.children(None) {
+        write_state(&mut out, document, &names, state, 1, &mut unmapped)?;
+    }
+    for history in document.history_states(None) {
+        unmapped.push(format!(
+            "History state {} isn't nested in a state, so it's left out",
+            history.name
+        ));
+    }
+    writeln!(out, "</scxml>")?;
+
+    Ok(ScxmlExport {
+        scxml: out,
+        unmapped,
+    })
+}
+
+/// Writes `state` with everything nested in it, indented by `depth` levels.
+fn write_state(
+    out: &mut String,
+    document: &Document,
+    names: &Names,
+    state: &DocumentState,
+    depth: usize,
+    unmapped: &mut Vec<String>,
+) -> Result {
+    let indent = "  ".repeat(depth);
+    let transitions = document
+        .transitions
+        .iter()
+        .filter(|transition| transition.from == state.id)
+        .collect::<Vec<_>>();
+    let children = document.children(Some(&state.id)).collect::<Vec<_>>();
+    let id = xml_escape(&names.states[&state.id]);
+    let on_entry = names.actions(&state.on_entry, unmapped);
+    let on_exit = names.actions(&state.on_exit, unmapped);
+    let has_actions = !(on_entry.is_empty() && on_exit.is_empty());
+    if state.terminal && children.is_empty() {
+        // A <final> can't have transitions, and the machine never takes them anyway
+        if !transitions.is_empty() {
+            unmapped.push(format!("Transitions out of terminal state {}", state.name));
+        }
+        if !has_actions {
+            writeln!(out, r#"{indent}<final id="{id}"/>"#)?;
+            return Ok(());
+        }
+        writeln!(out, r#"{indent}<final id="{id}">"#)?;
+        write_content(out, &indent, "onentry", &on_entry)?;
+        write_content(out, &indent, "onexit", &on_exit)?;
+        writeln!(out, "{indent}</final>")?;
+        return Ok(());
+    }
+    if state.terminal {
+        unmapped.push(format!(
+            "Terminal state {} has states nested in it, so it's exported as a plain state",
+            state.name
+        ));
+    }
+    if transitions.is_empty() && children.is_empty() && !has_actions {
+        writeln!(out, r#"{indent}<state id="{id}"/>"#)?;
+        return Ok(());
+    }
+    // Without regions there's nothing to run side by side, so it's a plain state
+    let tag = if state.parallel && !children.is_empty() {
+        "parallel"
+    } else {
+        "state"
+    };
+    write!(out, r#"{indent}<{tag} id="{id}""#)?;
+    // All regions of a <parallel> start at once, so it has no initial child
+    if let Some(initial) = children
+        .iter()
+        .find(|child| child.initial)
+        .filter(|_| tag == "state")
+    {
+        write!(
+            out,
+            r#" initial="{}""#,
+            xml_escape(&names.states[&initial.id])
+        )?;
+    }
+    writeln!(out, ">")?;
+    write_content(out, &indent, "onentry", &on_entry)?;
+    write_content(out, &indent, "onexit", &on_exit)?;
+    for child in children.iter() {
+        write_state(out, document, names, child, depth + 1, unmapped)?;
+    }
+    for history in document.history_states(Some(&state.id)) {
+        let kind = match history.history {
+            Some(HistoryKind::Deep) => "deep",
+            _ => "shallow",
+        };
+        let id = xml_escape(&names.states[&history.id]);
+        // Until the parent has been left it starts as usual, which SCXML wants spelled out
+        match children
+            .iter()
+            .find(|child| child.initial)
+            .filter(|_| tag == "state")
+        {
+            Some(initial) => {
+                writeln!(out, r#"{indent}  <history id="{id}" type="{kind}">"#)?;
+                writeln!(
+                    out,
+                    r#"{indent}    <transition target="{}"/>"#,
+                    xml_escape(&names.states[&initial.id])
+                )?;
+                writeln!(out, "{indent}  </history>")?;
+            }
+            None => writeln!(out, r#"{indent}  <history id="{id}" type="{kind}"/>"#)?,
+        }
+    }
+    for transition in transitions {
+        let target = document
+            .state(&transition.to)
+            .ok_or("Transition target not found")?;
+        let (event, mut conditions) = match &transition.guard {
+            Some(guard) => names.split_event(guard, unmapped),
+            None => (None, vec![]),
+        };
+        conditions.extend(names.matches(target, unmapped));
+        write!(out, "{indent}  <transition")?;
+        if let Some(event) = event {
+            write!(out, r#" event="{}""#, xml_escape(&event))?;
+        }
+        if !conditions.is_empty() {
+            write!(out, r#" cond="{}""#, xml_escape(&conditions.join(" && ")))?;
+        }
+        write!(
+            out,
+            r#" target="{}""#,
+            xml_escape(&names.states[&transition.to])
+        )?;
+        let actions = names.actions(&transition.actions, unmapped);
+        if actions.is_empty() {
+            writeln!(out, "/>")?;
+            continue;
+        }
+        writeln!(out, ">")?;
+        for action in actions {
+            writeln!(out, "{indent}    {action}")?;
+        }
+        writeln!(out, "{indent}  </transition>")?;
+    }
+    writeln!(out, "{indent}</{tag}>")?;
+    Ok(())
+}
+
+/// `content` wrapped in `<tag>`, nested in the element at `indent`, or nothing without content.
+fn write_content(out: &mut String, indent: &str, tag: &str, content: &[String]) -> Result {
+    if content.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "{indent}  <{tag}>")?;
+    for line in content {
+        writeln!(out, "{indent}    {line}")?;
+    }
+    writeln!(out, "{indent}  </{tag}>")?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Or,
+    And,
+    Compare,
+    Unary,
+}
+
+/// SCXML ids for states, and ECMAScript identifiers or event names for state types.
+struct Names<'a> {
+    state_types: &'a Vec<StateTypeData>,
+    states: HashMap<StateNodeId, String>,
+    variables: HashMap<StateId, String>,
+}
+
+impl<'a> Names<'a> {
+    fn new(document: &'a Document) -> Self {
+        let mut used = HashSet::new();
+        let states = document
+            .states
+            .iter()
+            .map(|state| (state.id.clone(), unique(&mut used, identifier(&state.name))))
+            .collect();
+        let mut used = HashSet::from(["_event".to_string()]);
+        let variables = document
+            .state_types
+            .iter()
+            .map(|data| (data.id.clone(), unique(&mut used, identifier(&data.name))))
+            .collect();
+        Self {
+            state_types: &document.state_types,
+            states,
+            variables,
+        }
+    }
+
+    /// Takes a trigger out of the top level `and`s of `guard` to use as the transition's event,
+    /// and returns the rest as conditions.
+    fn split_event(
+        &self,
+        guard: &Guard,
+        unmapped: &mut Vec<String>,
+    ) -> (Option<String>, Vec<String>) {
+        let mut conjuncts = vec![];
+        let mut pending = vec![guard];
+        while let Some(guard) = pending.pop() {
+            match guard {
+                Guard::And(a, b) => pending.extend([b.as_ref(), a.as_ref()]),
+                guard => conjuncts.push(guard),
+            }
+        }
+        let event = conjuncts.iter().position(|guard| {
+            matches!(guard, Guard::Variable(variable)
+                if self.state_type(variable) == Some(&StateType::Trigger))
+        });
+        let event = event.map(|index| match conjuncts.remove(index) {
+            Guard::Variable(variable) => self.variables[variable].clone(),
+            _ => unreachable!(),
+        });
+        let conditions = conjuncts
+            .into_iter()
+            .map(|guard| self.js(guard, Precedence::And, unmapped))
+            .collect();
+        (event, conditions)
+    }
+
+    /// Executable content that does what `actions` do, an element per line.
+    fn actions(&self, actions: &[Action], unmapped: &mut Vec<String>) -> Vec<String> {
+        let mut content = vec![];
+        for action in actions {
+            match action {
+                Action::Set { variable, value } => match (self.state_type(variable), value) {
+                    (None, _) => unmapped
+                        .push("An action sets a state variable that no longer exists".to_string()),
+                    // Triggers are events, which can be raised but not taken back
+                    (
+                        Some(StateType::Trigger),
+                        StateTypeValue::Trigger(true) | StateTypeValue::Bool(true),
+                    ) => content.push(format!(
+                        r#"<raise event="{}"/>"#,
+                        xml_escape(&self.variables[variable])
+                    )),
+                    (Some(StateType::Trigger), _) => {
+                        unmapped.push(format!("Clearing trigger {}", self.variables[variable]))
+                    }
+                    (Some(_), value) => content.push(format!(
+                        r#"<assign location="{}" expr="{}"/>"#,
+                        xml_escape(&self.variables[variable]),
+                        xml_escape(&js_value(value))
+                    )),
+                },
+                Action::Emit(event) => {
+                    // Fires the trigger of the same name, which has its own event name here, and
+                    // SCXML event names can't have spaces either way
+                    let event = self
+                        .state_types
+                        .iter()
+                        .find(|data| data.name == *event && data.state_type == StateType::Trigger)
+                        .map_or_else(
+                            || identifier(event),
+                            |data| self.variables[&data.id].clone(),
+                        );
+                    content.push(format!(r#"<raise event="{}"/>"#, xml_escape(&event)));
+                }
+                Action::StartTimer(variable) | Action::StopTimer(variable) => {
+                    let name = self
+                        .variables
+                        .get(variable)
+                        .map_or("<missing>", String::as_str);
+                    unmapped.push(format!("Timer {name}, which SCXML has no equivalent of"));
+                }
+            }
+        }
+        content
+    }
+
+    fn state_type(&self, variable: &StateId) -> Option<&StateType> {
+        self.state_types
+            .state_type(variable)
+            .map(|data| &data.state_type)
+    }
+
+    /// What the inputs have to be for `state` to be entered.
+    fn matches(&self, state: &DocumentState, unmapped: &mut Vec<String>) -> Vec<String> {
+        state
+            .state
+            .iter()
+            .filter(|value| !value.any)
+            .map(|value| {
+                let guard = Guard::Compare {
+                    variable: value.state.clone(),
+                    op: CompareOp::Eq,
+                    value: value.value.clone(),
+                };
+                self.js(&guard, Precedence::And, unmapped)
+            })
+            .collect()
+    }
+
+    fn js(&self, guard: &Guard, context: Precedence, unmapped: &mut Vec<String>) -> String {
+        let missing = |unmapped: &mut Vec<String>| {
+            unmapped.push("A condition reads a state variable that no longer exists".to_string());
+            (Precedence::Unary, "false".to_string())
+        };
+        let (precedence, text) = match guard {
+            Guard::Literal(value) => (Precedence::Unary, value.to_string()),
+            Guard::Variable(variable) => match self.state_type(variable) {
+                Some(StateType::Trigger) => (
+                    Precedence::Compare,
+                    format!("_event.name == '{}'", self.variables[variable]),
+                ),
+                Some(StateType::Bool) => (Precedence::Unary, self.variables[variable].clone()),
+                Some(_) => (Precedence::Unary, "false".to_string()),
+                None => missing(unmapped),
+            },
+            Guard::Compare {
+                variable,
+                op,
+                value,
+            } => match (self.state_type(variable), value) {
+                (None, _) => missing(unmapped),
+                (
+                    Some(StateType::Trigger),
+                    StateTypeValue::Bool(fired) | StateTypeValue::Trigger(fired),
+                ) if matches!(op, CompareOp::Eq | CompareOp::Ne) => {
+                    let op = if *fired == (*op == CompareOp::Eq) {
+                        "=="
+                    } else {
+                        "!="
+                    };
+                    (
+                        Precedence::Compare,
+                        format!("_event.name {op} '{}'", self.variables[variable]),
+                    )
+                }
+                (Some(StateType::Trigger), _) => (Precedence::Unary, "false".to_string()),
+                (Some(_), value) => (
+                    Precedence::Compare,
+                    format!(
+                        "{} {} {}",
+                        self.variables[variable],
+                        op.symbol(),
+                        js_value(value)
+                    ),
+                ),
+            },
+            Guard::Not(guard) => (
+                Precedence::Unary,
+                format!("!{}", self.js(guard, Precedence::Unary, unmapped)),
+            ),
+            Guard::And(a, b) => (
+                Precedence::And,
+                format!(
+                    "{} && {}",
+                    self.js(a, Precedence::And, unmapped),
+                    self.js(b, Precedence::And, unmapped)
+                ),
+            ),
+            Guard::Or(a, b) => (
+                Precedence::Or,
+                format!(
+                    "{} || {}",
+                    self.js(a, Precedence::Or, unmapped),
+                    self.js(b, Precedence::Or, unmapped)
+                ),
+            ),
+        };
+        if precedence < context {
+            format!("({text})")
+        } else {
+            text
+        }
+    }
+}
+
+fn js_value(value: &StateTypeValue) -> String {
+    match value {
+        StateTypeValue::Bool(value) | StateTypeValue::Trigger(value) => value.to_string(),
+        StateTypeValue::Int(value) => value.to_string(),
+        StateTypeValue::Float(value) if value.is_nan() => "NaN".to_string(),
+        StateTypeValue::Float(value) if value.is_infinite() => if *value > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        }
+        .to_string(),
+        StateTypeValue::Float(value) => format!("{value:?}"),
+        StateTypeValue::Enum(variant) => {
+            format!("'{}'", variant.replace('\\', "\\\\").replace('\'', "\\'"))
+        }
+    }
+}
+
+/// Works both as an SCXML id and as an ECMAScript identifier.
+fn identifier(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else if JS_RESERVED.contains(&name.as_str()) {
+        format!("{name}_")
+    } else {
+        name
+    }
+}
+
+fn unique(used: &mut HashSet<String>, name: String) -> String {
+    let name = (1..)
+        .map(|n| match n {
+            1 => name.clone(),
+            n => format!("{name}_{n}"),
+        })
+        .find(|candidate| !used.contains(candidate))
+        .unwrap();
+    used.insert(name.clone());
+    name
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const JS_RESERVED: [&str; 40] = [
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "null",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::fixtures::{document, guarded, state};
+
+    /// Everything about the states that survives a round trip, by name.
+    fn describe_states(document: &Document) -> Vec<String> {
+        let mut states = document
+            .states
+            .iter()
+            .map(|state| {
+                let parent = state
+                    .parent
+                    .as_ref()
+                    .and_then(|parent| document.state(parent))
+                    .map(|parent| parent.name.as_str());
+                format!(
+                    "{} in {parent:?}, parallel {}, history {:?}, initial {}, terminal {}",
+                    state.name, state.parallel, state.history, state.initial, state.terminal
+                )
+            })
+            .collect::<Vec<_>>();
+        states.sort();
+        states
+    }
+
+    fn describe_transitions(document: &Document) -> Vec<String> {
+        let name = |id| document.state(id).unwrap().name.as_str();
+        let mut transitions = document
+            .transitions
+            .iter()
+            .map(|transition| {
+                format!(
+                    "{} -> {}: {}",
+                    name(&transition.from),
+                    name(&transition.to),
+                    transition.label(&document.state_types)
+                )
+            })
+            .collect::<Vec<_>>();
+        transitions.sort();
+        transitions
+    }
+
+    #[test]
+    fn nested_parallel_and_history_states_round_trip() {
+        let state_types = vec![
+            StateTypeData::new("Grounded", StateType::Bool),
+            StateTypeData::new("Jump", StateType::Trigger),
+            StateTypeData::new("Lives", StateType::Int),
+        ];
+        let idle = state("Idle", None, true);
+        let mut air = state("Air", None, false);
+        air.parallel = true;
+        let motion = state("Motion", Some(&air), true);
+        let rising = state("Rising", Some(&motion), true);
+        let falling = state("Falling", Some(&motion), false);
+        let mut resume = state("Resume", Some(&motion), false);
+        resume.history = Some(HistoryKind::Deep);
+        let arms = state("Arms", Some(&air), false);
+        let still = state("Still", Some(&arms), true);
+        let mut done = state("Done", None, false);
+        done.terminal = true;
+        let transitions = vec![
+            guarded(&idle, &air, "Jump and Lives > 0", &state_types),
+            guarded(&rising, &falling, "not Grounded", &state_types),
+            guarded(&air, &idle, "Grounded or Lives < 1", &state_types),
+            guarded(
+                &idle,
+                &resume,
+                "Jump and Grounded and Lives > 1",
+                &state_types,
+            ),
+            guarded(&idle, &done, "Lives == 0", &state_types),
+        ];
+        let document = document(
+            state_types,
+            &[
+                &idle, &air, &motion, &rising, &falling, &resume, &arms, &still, &done,
+            ],
+            transitions,
+        );
+
+        let export = export_scxml(&document).unwrap();
+        assert!(export.unmapped.is_empty(), "{:?}", export.unmapped);
+        let import = import_scxml(&export.scxml).unwrap();
+        assert!(import.unmapped.is_empty(), "{:?}", import.unmapped);
+        assert_eq!(
+            describe_states(&import.document),
+            describe_states(&document)
+        );
+        assert_eq!(
+            describe_transitions(&import.document),
+            describe_transitions(&document)
+        );
+    }
+
+    #[test]
+    fn trigger_in_the_top_level_ands_becomes_the_event() {
+        let state_types = vec![
+            StateTypeData::new("Grounded", StateType::Bool),
+            StateTypeData::new("Jump", StateType::Trigger),
+            StateTypeData::new("Lives", StateType::Int),
+        ];
+        let document = document(state_types.clone(), &[], vec![]);
+        let names = Names::new(&document);
+        let split = |source: &str| {
+            let guard = Guard::parse(source, &state_types).unwrap();
+            names.split_event(&guard, &mut vec![])
+        };
+        assert_eq!(
+            split("Grounded and Jump and Lives > 0"),
+            (
+                Some("Jump".to_string()),
+                vec!["Grounded".to_string(), "Lives > 0".to_string()]
+            )
+        );
+        assert_eq!(
+            split("Grounded or Jump"),
+            (
+                None,
+                vec!["(Grounded || _event.name == 'Jump')".to_string()]
+            )
+        );
+        assert_eq!(
+            split("Jump and (Grounded or Lives > 2)"),
+            (
+                Some("Jump".to_string()),
+                vec!["(Grounded || Lives > 2)".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn js_parenthesizes_by_precedence() {
+        let state_types = vec![
+            StateTypeData::new("A", StateType::Bool),
+            StateTypeData::new("B", StateType::Bool),
+            StateTypeData::new("C", StateType::Bool),
+        ];
+        let document = document(state_types.clone(), &[], vec![]);
+        let names = Names::new(&document);
+        let js = |source: &str| {
+            let guard = Guard::parse(source, &state_types).unwrap();
+            names.js(&guard, Precedence::Or, &mut vec![])
+        };
+        assert_eq!(js("(A or B) and C"), "(A || B) && C");
+        assert_eq!(js("A or B and C"), "A || B && C");
+        assert_eq!(js("not (A and B)"), "!(A && B)");
+        assert_eq!(js("not A and B"), "!A && B");
+        assert_eq!(js("A and not (B or C)"), "A && !(B || C)");
+    }
+}
@@ -0,0 +1,469 @@
+//! Play mode: runs the machine being edited against inputs set in the side panel.
+
+use std::time::Duration;
+
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+
+use crate::bindings::no_bound_key_pressed;
+use crate::document::{CurrentDocument, DocumentLoaded};
+use crate::editor::{ResumedText, SidePanel, SidePanelEntry, value_color};
+use crate::history::History;
+use crate::runtime::{Runtime, RuntimeEvent};
+use crate::text_input::{TextInput, TextInputActive, TextInputUnfocused, no_text_input_active};
+use crate::{State, StateId, StateType, StateTypeValue, StateTypes, Transition};
+
+/// How long the machine waits between steps while playing, unless a timed transition is due
+/// earlier.
+const STEP_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the noodle of a transition that was just taken stays lit.
+const FLASH_DURATION: Duration = Duration::from_millis(400);
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                simulation_shortcuts.run_if(no_text_input_active.and(no_bound_key_pressed)),
+                (
+                    follow_edits,
+                    step_simulation.after(follow_edits),
+                    add_live_inputs,
+                    update_live_inputs.after(step_simulation),
+                )
+                    .run_if(resource_exists::<Simulation>),
+                highlight_active_node,
+                show_resumed_states,
+                update_play_button,
+            ),
+        )
+        .add_observer(add_play_button)
+        .add_observer(play)
+        .add_observer(stop)
+        .add_observer(stop_on_load);
+    }
+}
+
+/// Starts running the machine from its initial state.
+#[derive(Event, Debug, Default)]
+pub struct Play;
+
+/// Stops the machine and goes back to editing.
+#[derive(Event, Debug, Default)]
+pub struct Stop;
+
+/// The machine while it's being played, only exists in play mode.
+#[derive(Resource, Debug)]
+pub struct Simulation {
+    pub runtime: Runtime,
+    timer: Timer,
+    flash: Option<(Vec<Transition>, Timer)>,
+}
+
+impl Simulation {
+    pub fn new(runtime: Runtime) -> Self {
+        Self {
+            runtime,
+            timer: Timer::new(STEP_INTERVAL, TimerMode::Repeating),
+            flash: None,
+        }
+    }
+
+    /// The transitions that were just taken, with how much of their flash is left, from 1 to 0.
+    pub fn flash(&self) -> Option<(&[Transition], f32)> {
+        self.flash
+            .as_ref()
+            .map(|(transitions, timer)| (transitions.as_slice(), timer.fraction_remaining()))
+    }
+}
+
+/// Toggles play mode.
+#[derive(Component)]
+struct PlayButton;
+
+/// A live value in the side panel, set on the running machine instead of the nodes.
+#[derive(Component)]
+struct LiveInput(StateId);
+
+/// Holds a [`LiveInput`] and its label, removed again when play mode stops.
+#[derive(Component)]
+struct LiveInputRow;
+
+/// A side panel entry that has its [`LiveInput`].
+#[derive(Component)]
+struct HasLiveInput;
+
+fn add_play_button(trigger: Trigger<OnAdd, SidePanel>, mut commands: Commands) {
+    commands
+        .spawn((
+            Node {
+                justify_content: JustifyContent::Center,
+                margin: UiRect::bottom(Val::Px(10.0)),
+                padding: UiRect::all(Val::Px(5.0)),
+                ..default()
+            },
+            BackgroundColor(css::DARK_GREEN.into()),
+            BorderRadius::all(Val::Px(3.0)),
+            Button,
+            PlayButton,
+            ChildOf(trigger.target()),
+            children![Text("Play".to_string())],
+        ))
+        .observe(click_play_button);
+}
+
+fn click_play_button(
+    _trigger: Trigger<Pointer<Click>>,
+    simulation: Option<Res<Simulation>>,
+    mut commands: Commands,
+) {
+    if simulation.is_some() {
+        commands.trigger(Stop);
+    } else {
+        commands.trigger(Play);
+    }
+}
+
+fn update_play_button(
+    simulation: Option<Res<Simulation>>,
+    buttons: Query<(&Children, &mut BackgroundColor), With<PlayButton>>,
+    mut texts: Query<&mut Text>,
+) {
+    let playing = simulation.is_some();
+    for (children, mut background) in buttons {
+        background.set_if_neq(BackgroundColor(if playing {
+            css::MAROON.into()
+        } else {
+            css::DARK_GREEN.into()
+        }));
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.set_if_neq(Text(if playing { "Stop" } else { "Play" }.to_string()));
+            }
+        }
+    }
+}
+
+fn simulation_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    simulation: Option<Res<Simulation>>,
+    mut commands: Commands,
+) {
+    if keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+        return;
+    }
+    if keys.just_pressed(KeyCode::KeyP) {
+        if simulation.is_some() {
+            commands.trigger(Stop);
+        } else {
+            commands.trigger(Play);
+        }
+    }
+}
+
+fn play(_trigger: Trigger<Play>, current: CurrentDocument, mut commands: Commands) -> Result {
+    // E.g. without an initial state, which is a problem to fix rather than a crash
+    let runtime = match Runtime::new(current.document()) {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            warn!("Can't play: {error}");
+            return Ok(());
+        }
+    };
+    info!(
+        "Playing from {}",
+        runtime
+            .current()
+            .iter()
+            .map(|state| state.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    commands.insert_resource(Simulation::new(runtime));
+    Ok(())
+}
+
+fn stop(
+    _trigger: Trigger<Stop>,
+    rows: Query<Entity, With<LiveInputRow>>,
+    entries: Query<Entity, With<HasLiveInput>>,
+    mut commands: Commands,
+) {
+    commands.remove_resource::<Simulation>();
+    for row in rows.iter() {
+        commands.entity(row).despawn();
+    }
+    for entry in entries.iter() {
+        commands.entity(entry).remove::<HasLiveInput>();
+    }
+}
+
+fn stop_on_load(
+    _trigger: Trigger<DocumentLoaded>,
+    simulation: Option<Res<Simulation>>,
+    mut commands: Commands,
+) {
+    if simulation.is_some() {
+        commands.trigger(Stop);
+    }
+}
+
+/// Restarts the machine on the edited document, keeping the current states, inputs and history
+/// where they still exist.
+fn follow_edits(
+    history: Res<History>,
+    current: CurrentDocument,
+    mut simulation: ResMut<Simulation>,
+    mut commands: Commands,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    let document = current.document();
+    let at = simulation
+        .runtime
+        .current()
+        .iter()
+        .map(|state| state.id.clone())
+        .filter(|id| document.state(id).is_some())
+        .collect::<Vec<_>>();
+    let runtime = if !at.is_empty() {
+        Runtime::starting_in(document, &at)
+    } else {
+        Runtime::new(document)
+    };
+    let mut runtime = match runtime {
+        Ok(runtime) => runtime,
+        Err(error) => {
+            warn!("Stopped playing: {error}");
+            commands.trigger(Stop);
+            return;
+        }
+    };
+    runtime.continue_from(&simulation.runtime);
+    for data in runtime.document().state_types.clone() {
+        if let Some(value) = simulation.runtime.input(&data.id) {
+            // Values that don't fit the edited type anymore start over at the default
+            let _ = runtime.set_input(&data.id, value.clone());
+        }
+    }
+    simulation.runtime = runtime;
+}
+
+pub fn step_simulation(time: Res<Time>, mut simulation: ResMut<Simulation>) {
+    let simulation = simulation.as_mut();
+    if let Some((_, flash)) = &mut simulation.flash {
+        flash.tick(time.delta());
+        if flash.finished() {
+            simulation.flash = None;
+        }
+    }
+    simulation.timer.tick(time.delta());
+    simulation.runtime.advance(time.delta_secs());
+    // Timed transitions are taken as soon as their delay has passed, the rest on the beat
+    let events = if simulation.timer.just_finished() {
+        simulation.runtime.step()
+    } else {
+        simulation.runtime.step_timed()
+    };
+    let mut transitions = vec![];
+    for event in events {
+        match event {
+            RuntimeEvent::Emitted(name) => info!("Emitted {name}"),
+            RuntimeEvent::Took(transition) => transitions.push(transition),
+            RuntimeEvent::Exited(_) | RuntimeEvent::Entered(_) => {}
+        }
+    }
+    if !transitions.is_empty() {
+        debug!("Took {transitions:?}");
+        simulation.flash = Some((transitions, Timer::new(FLASH_DURATION, TimerMode::Once)));
+    }
+}
+
+fn add_live_inputs(
+    entries: Query<(Entity, &SidePanelEntry), Without<HasLiveInput>>,
+    state_types: Res<StateTypes>,
+    mut commands: Commands,
+) {
+    for (entry, SidePanelEntry(id)) in entries.iter() {
+        let Some(data) = state_types.get(id) else {
+            continue;
+        };
+        commands.entity(entry).insert(HasLiveInput);
+        let row = commands
+            .spawn((
+                Node {
+                    align_items: AlignItems::Center,
+                    margin: UiRect::top(Val::Px(5.0)),
+                    ..default()
+                },
+                LiveInputRow,
+                ChildOf(entry),
+                children![(
+                    Text("Live".to_string()),
+                    TextFont::from_font_size(12.0),
+                    Node {
+                        margin: UiRect::right(Val::Px(5.0)),
+                        ..default()
+                    },
+                )],
+            ))
+            .id();
+        // Filled in by `update_live_inputs`
+        if matches!(data.state_type, StateType::Bool | StateType::Trigger) {
+            commands
+                .spawn((
+                    Node {
+                        flex_grow: 1.0,
+                        justify_content: JustifyContent::Center,
+                        border: UiRect::all(Val::Px(3.0)),
+                        ..default()
+                    },
+                    BorderColor(css::BLACK.into()),
+                    BorderRadius::all(Val::Px(3.0)),
+                    Button,
+                    LiveInput(id.clone()),
+                    ChildOf(row),
+                    children![Text::default()],
+                ))
+                .observe(toggle_live_input);
+        } else {
+            commands
+                .spawn((
+                    Node {
+                        flex_grow: 1.0,
+                        border: UiRect::all(Val::Px(3.0)),
+                        padding: UiRect::horizontal(Val::Px(3.0)),
+                        ..default()
+                    },
+                    TextInput::default(),
+                    BackgroundColor(css::GRAY.into()),
+                    BorderColor(css::BLACK.into()),
+                    LiveInput(id.clone()),
+                    ChildOf(row),
+                ))
+                .observe(set_live_input);
+        }
+    }
+}
+
+/// Shows the running machine's inputs, which change when triggers are used up.
+fn update_live_inputs(
+    simulation: Res<Simulation>,
+    mut toggles: Query<(&LiveInput, &Children, &mut BackgroundColor), Without<TextInput>>,
+    mut text_inputs: Query<(&LiveInput, &mut TextInput, &TextInputActive)>,
+    mut texts: Query<&mut Text>,
+) {
+    let runtime = &simulation.runtime;
+    for (LiveInput(id), children, mut background) in toggles.iter_mut() {
+        let Some(value) = runtime.input(id) else {
+            continue;
+        };
+        background.set_if_neq(BackgroundColor(value_color(value)));
+        for child in children.iter() {
+            if let Ok(mut text) = texts.get_mut(child) {
+                text.set_if_neq(Text(value.to_string()));
+            }
+        }
+    }
+    for (LiveInput(id), mut text_input, active) in text_inputs.iter_mut() {
+        // Don't overwrite what's being typed
+        if *active == TextInputActive::Active {
+            continue;
+        }
+        let Some(value) = runtime.input(id) else {
+            continue;
+        };
+        let label = value.to_string();
+        if text_input.0 != label {
+            text_input.0 = label;
+        }
+    }
+}
+
+fn toggle_live_input(
+    trigger: Trigger<Pointer<Click>>,
+    live_inputs: Query<&LiveInput>,
+    mut simulation: ResMut<Simulation>,
+) -> Result {
+    let LiveInput(id) = live_inputs.get(trigger.target())?;
+    let new = match simulation.runtime.input(id) {
+        Some(StateTypeValue::Bool(value)) => StateTypeValue::Bool(!value),
+        // A trigger stays set until the next step uses it up
+        Some(StateTypeValue::Trigger(_)) => StateTypeValue::Trigger(true),
+        _ => {
+            warn!("Only bool and trigger inputs can be toggled");
+            return Ok(());
+        }
+    };
+    simulation.runtime.set_input(id, new)
+}
+
+fn set_live_input(
+    trigger: Trigger<TextInputUnfocused>,
+    mut text_inputs: Query<(&LiveInput, &mut TextInput)>,
+    state_types: Res<StateTypes>,
+    mut simulation: ResMut<Simulation>,
+) -> Result {
+    let (LiveInput(id), mut text_input) = text_inputs.get_mut(trigger.target())?;
+    let data = state_types.get(id).ok_or("StateType not found")?;
+    let set = data
+        .state_type
+        .parse_value(&text_input.0)
+        .is_some_and(|value| simulation.runtime.set_input(id, value).is_ok());
+    if set {
+        return Ok(());
+    }
+    // Not a valid value, so show the one the machine still has
+    if let Some(value) = simulation.runtime.input(id) {
+        text_input.0 = value.to_string();
+    }
+    Ok(())
+}
+
+/// Colors the nodes the machine is in while playing, including the ones around the current states.
+fn highlight_active_node(
+    simulation: Option<Res<Simulation>>,
+    nodes: Query<(&State, &mut BackgroundColor)>,
+) {
+    let active = simulation
+        .as_ref()
+        .map(|simulation| simulation.runtime.active())
+        .unwrap_or_default();
+    for (state, mut background) in nodes {
+        let color = if active.iter().any(|active| active.id == state.id) {
+            css::DARK_GREEN
+        } else {
+            css::MAROON
+        };
+        background.set_if_neq(BackgroundColor(color.into()));
+    }
+}
+
+/// Names what each history pseudo-state would resume while playing, nothing while editing.
+fn show_resumed_states(
+    simulation: Option<Res<Simulation>>,
+    texts: Query<(&ResumedText, &mut Text)>,
+) {
+    for (ResumedText(id), mut text) in texts {
+        let resumed = simulation
+            .as_ref()
+            .map(|simulation| simulation.runtime.resumed(id))
+            .unwrap_or_default();
+        let label = if resumed.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "-> {}",
+                resumed
+                    .iter()
+                    .map(|state| state.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        text.set_if_neq(Text(label));
+    }
+}
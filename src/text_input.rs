@@ -12,6 +12,7 @@ impl Plugin for TextInputPlugin {
                 focus_text_fields_keyboard,
                 keyboard_input,
                 update_text.after(keyboard_input),
+                update_font,
             )
                 .in_set(TextInputSystemSet),
         )
@@ -45,6 +46,13 @@ pub enum TextInputActive {
 #[derive(SystemSet, Hash, Debug, Clone, Eq, PartialEq)]
 pub struct TextInputSystemSet;
 
+/// Run condition for keyboard shortcuts that shouldn't fire while typing.
+pub fn no_text_input_active(text_inputs: Query<&TextInputActive>) -> bool {
+    !text_inputs
+        .iter()
+        .any(|active| *active == TextInputActive::Active)
+}
+
 fn focus_text_fields_mouse(
     buttons: Res<ButtonInput<MouseButton>>,
     mut text_inputs: Query<(Entity, &mut TextInputActive, &Interaction)>,
@@ -89,16 +97,18 @@ fn focus_text_fields_keyboard(
     }
 }
 
+/// A [`TextFont`] on the [`TextInput`] itself is used for the text inside it.
 fn create_rest_of_input(
     trigger: Trigger<OnAdd, TextInput>,
-    text_inputs: Query<&TextInput>,
+    text_inputs: Query<(&TextInput, Option<&TextFont>)>,
     mut commands: Commands,
 ) -> Result {
     let text_input_entity = trigger.target();
-    let text_input = text_inputs.get(text_input_entity)?;
+    let (text_input, font) = text_inputs.get(text_input_entity)?;
     let text_input_text_entity = commands
         .spawn((
             Text(text_input.0.clone()),
+            font.cloned().unwrap_or_default(),
             TextLayout {
                 linebreak: LineBreak::WordOrCharacter,
                 ..default()
@@ -158,3 +168,14 @@ fn update_text(
         }
     }
 }
+
+fn update_font(
+    text_inputs: Query<(&TextFont, &TextInputText), Changed<TextFont>>,
+    mut text_input_texts: Query<&mut TextFont, Without<TextInputText>>,
+) {
+    for (font, text_input_text) in text_inputs.iter() {
+        if let Ok(mut text_font) = text_input_texts.get_mut(text_input_text.0) {
+            *text_font = font.clone();
+        }
+    }
+}
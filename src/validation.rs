@@ -0,0 +1,667 @@
+//! Checks the machine after every edit and lists what's wrong with it in a problems panel.
+
+use bevy::color::palettes::css;
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+
+use crate::canvas::{Canvas, CanvasPosition};
+use crate::document::{CurrentDocument, Document, DocumentState};
+use crate::editor::{GuardTextInput, MainSpace, Selected};
+use crate::hierarchy::{EnterScope, LaneLayout, Lanes};
+use crate::history::History;
+use crate::text_input::{TextInputActive, TextInputFocused};
+use crate::{State, StateNodeId, StateTypeLookup, Transition};
+
+pub struct ValidationPlugin;
+
+impl Plugin for ValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Problems>()
+            .add_systems(
+                Update,
+                (
+                    update_problems,
+                    update_problems_panel.after(update_problems),
+                ),
+            )
+            .add_observer(add_problems_panel)
+            .add_observer(focus_state)
+            .add_observer(focus_transition);
+    }
+}
+
+/// What a [`Problem`] is about, so it can be shown.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProblemSubject {
+    Document,
+    State(StateNodeId),
+    Transition { from: StateNodeId, to: StateNodeId },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub message: String,
+    pub subject: ProblemSubject,
+}
+
+/// Everything [`validate`] found in the machine as it is in the editor.
+#[derive(Resource, Debug, Default, Deref)]
+pub struct Problems(pub Vec<Problem>);
+
+/// Everything that keeps the machine from running as drawn, one sentence each.
+pub fn validate(document: &Document) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut report = |subject: ProblemSubject, message: String| {
+        let problem = Problem { message, subject };
+        if !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    };
+    let transition_subject = |transition: &Transition| ProblemSubject::Transition {
+        from: transition.from.clone(),
+        to: transition.to.clone(),
+    };
+    let name = |id: &StateNodeId| {
+        document
+            .state(id)
+            .map_or("<missing>".to_string(), |state| state.name.clone())
+    };
+
+    match document
+        .children(None)
+        .filter(|state| state.initial)
+        .count()
+    {
+        0 => report(
+            ProblemSubject::Document,
+            "No state is marked as initial".to_string(),
+        ),
+        1 => {}
+        _ => report(
+            ProblemSubject::Document,
+            "More than one state is marked as initial".to_string(),
+        ),
+    }
+
+    for state in document.states.iter() {
+        let subject = || ProblemSubject::State(state.id.clone());
+        if state
+            .state
+            .iter()
+            .any(|value| document.state_types.state_type(&value.state).is_none())
+        {
+            report(
+                subject(),
+                format!(
+                    "{} has a value for a state variable that no longer exists",
+                    state.name
+                ),
+            );
+        }
+        for action in state.on_entry.iter().chain(state.on_exit.iter()) {
+            for problem in action.problems(&document.state_types) {
+                report(subject(), format!("{}: {problem}", state.name));
+            }
+        }
+        if state.history.is_some() && !(state.on_entry.is_empty() && state.on_exit.is_empty()) {
+            report(
+                subject(),
+                format!(
+                    "{} is a history state, which the machine is never in, so its actions never \
+                     run",
+                    state.name
+                ),
+            );
+        }
+        // The regions of a parallel state all start at once, so none of them has to be initial
+        if !state.parallel
+            && document
+                .children(Some(&state.id))
+                .filter(|child| child.initial)
+                .count()
+                > 1
+        {
+            report(
+                subject(),
+                format!("More than one state in {} is marked as initial", state.name),
+            );
+        }
+        if state.history.is_some() && state.parent.is_none() {
+            report(
+                subject(),
+                format!(
+                    "{} is a history state but isn't nested in a state to resume",
+                    state.name
+                ),
+            );
+        }
+        if state.parallel && document.children(Some(&state.id)).count() < 2 {
+            report(
+                subject(),
+                format!(
+                    "{} is parallel but has fewer than two regions to run side by side",
+                    state.name
+                ),
+            );
+        }
+        // Only the innermost states need a way out, which may also be one of the states around
+        // them
+        let around = [state]
+            .into_iter()
+            .chain(document.ancestors(&state.id))
+            .collect::<Vec<_>>();
+        if !document.is_composite(&state.id)
+            && state.history.is_none()
+            && !around.iter().any(|state| state.terminal)
+            && !document
+                .transitions
+                .iter()
+                .any(|transition| around.iter().any(|state| transition.from == state.id))
+        {
+            report(
+                subject(),
+                format!(
+                    "{} has no way out, mark it terminal if the machine should stop there",
+                    state.name
+                ),
+            );
+        }
+    }
+
+    for (index, state) in document.states.iter().enumerate() {
+        for other in document.states[..index].iter() {
+            if other.name == state.name {
+                report(
+                    ProblemSubject::State(state.id.clone()),
+                    format!("More than one state is called {}", state.name),
+                );
+            }
+            // Transitions pick between the states in the same parent, unless they're regions that
+            // are all active anyway
+            let regions = state
+                .parent
+                .as_ref()
+                .and_then(|parent| document.state(parent))
+                .is_some_and(|parent| parent.parallel);
+            if other.parent == state.parent
+                && !regions
+                && state.history.is_none()
+                && other.history.is_none()
+                && same_values(state, other)
+            {
+                report(
+                    ProblemSubject::State(state.id.clone()),
+                    format!(
+                        "{} and {} assign the same values, so transitions can't tell them apart",
+                        other.name, state.name
+                    ),
+                );
+            }
+        }
+    }
+
+    if let Some(initial) = document.initial_state() {
+        let reachable = reachable_from(document, &initial.id);
+        for state in document
+            .states
+            .iter()
+            .filter(|state| !reachable.contains(&state.id))
+        {
+            report(
+                ProblemSubject::State(state.id.clone()),
+                format!("{} can't be reached from {}", state.name, initial.name),
+            );
+        }
+    }
+
+    for transition in document.transitions.iter() {
+        let label = format!("{} -> {}", name(&transition.from), name(&transition.to));
+        if document
+            .state(&transition.from)
+            .is_some_and(|from| from.history.is_some())
+        {
+            report(
+                transition_subject(transition),
+                format!(
+                    "{} is a history state, which the machine is never in, so {label} is never \
+                     taken",
+                    name(&transition.from)
+                ),
+            );
+        }
+        if document
+            .state(&transition.from)
+            .is_some_and(|from| from.terminal)
+        {
+            report(
+                transition_subject(transition),
+                format!(
+                    "{} is terminal, so {label} is never taken",
+                    name(&transition.from)
+                ),
+            );
+        }
+        for action in transition.actions.iter() {
+            for problem in action.problems(&document.state_types) {
+                report(
+                    transition_subject(transition),
+                    format!("{label}: {problem}"),
+                );
+            }
+        }
+        let Some(guard) = &transition.guard else {
+            continue;
+        };
+        for problem in guard.problems(&document.state_types) {
+            report(
+                transition_subject(transition),
+                format!("{label}: {problem}"),
+            );
+        }
+        if !guard.can_hold(&document.state_types, &HashMap::new()) {
+            report(
+                transition_subject(transition),
+                format!("The guard of {label} can never be true"),
+            );
+        } else if let Some(to) = document.state(&transition.to) {
+            // Only taken when the inputs also match what the target assigns
+            let target = to
+                .state
+                .iter()
+                .filter(|value| !value.any)
+                .map(|value| (value.state.clone(), value.value.clone()))
+                .collect();
+            if !guard.can_hold(&document.state_types, &target) {
+                report(
+                    transition_subject(transition),
+                    format!(
+                        "{label} is never taken, its guard rules out the values {} assigns",
+                        to.name
+                    ),
+                );
+            }
+        }
+    }
+
+    problems
+}
+
+fn same_values(a: &DocumentState, b: &DocumentState) -> bool {
+    a.state.len() == b.state.len()
+        && a.state.iter().all(|value| {
+            b.state.iter().any(|other| {
+                other.state == value.state
+                    && other.any == value.any
+                    && (value.any || other.value == value.value)
+            })
+        })
+}
+
+/// Follows transitions from `start`, except the ones out of terminal states and what they're
+/// nested in. Being in a state means being in the states around it, so their transitions count
+/// too, but entering a state only enters the children the runtime would.
+fn reachable_from(document: &Document, start: &StateNodeId) -> HashSet<StateNodeId> {
+    let mut reachable = Reachable {
+        document,
+        reachable: HashSet::new(),
+        expanded: HashSet::new(),
+        queue: vec![],
+    };
+    reachable.enter(start);
+    while let Some(id) = reachable.queue.pop() {
+        let Some(state) = document.state(&id) else {
+            continue;
+        };
+        let stopped = state.terminal
+            || document
+                .ancestors(&id)
+                .iter()
+                .any(|ancestor| ancestor.terminal);
+        if stopped {
+            continue;
+        }
+        for transition in document
+            .transitions
+            .iter()
+            .filter(|transition| transition.from == id)
+        {
+            reachable.enter(&transition.to);
+        }
+    }
+    reachable.reachable
+}
+
+/// The search behind [`reachable_from`].
+struct Reachable<'a> {
+    document: &'a Document,
+    reachable: HashSet<StateNodeId>,
+    /// States entered from outside, whose initial child or regions are reachable as well.
+    expanded: HashSet<StateNodeId>,
+    /// Reachable states whose transitions haven't been followed yet.
+    queue: Vec<StateNodeId>,
+}
+
+impl Reachable<'_> {
+    fn mark(&mut self, id: &StateNodeId) {
+        if self.reachable.insert(id.clone()) {
+            self.queue.push(id.clone());
+        }
+    }
+
+    /// What a transition to `id` enters: the states around it, the other regions of parallel
+    /// ones among those, and `id` with its initial child or regions. A history pseudo-state
+    /// enters its parent, and only resumes states that were reachable before.
+    fn enter(&mut self, id: &StateNodeId) {
+        let document = self.document;
+        let Some(state) = document.state(id) else {
+            return;
+        };
+        if state.history.is_some() {
+            self.mark(id);
+            if let Some(parent) = &state.parent {
+                self.enter(parent);
+            }
+            return;
+        }
+        let mut inner = id;
+        for ancestor in document.ancestors(id) {
+            self.mark(&ancestor.id);
+            if ancestor.parallel {
+                for region in document
+                    .children(Some(&ancestor.id))
+                    .filter(|region| region.id != *inner)
+                {
+                    self.enter_down(&region.id);
+                }
+            }
+            inner = &ancestor.id;
+        }
+        self.enter_down(id);
+    }
+
+    /// Enters `id` from outside, and with it the child it starts in or all its regions.
+    fn enter_down(&mut self, id: &StateNodeId) {
+        self.mark(id);
+        if !self.expanded.insert(id.clone()) {
+            return;
+        }
+        let document = self.document;
+        let parallel = document.state(id).is_some_and(|state| state.parallel);
+        let mut children = document.children(Some(id)).collect::<Vec<_>>();
+        if !parallel {
+            // Without an initial child the runtime starts in the first one
+            children.sort_by_key(|child| !child.initial);
+            children.truncate(1);
+        }
+        for child in children {
+            self.enter_down(&child.id);
+        }
+    }
+}
+
+/// Every edit goes through the [`History`], so that changing is a cheap sign to check again.
+fn update_problems(
+    history: Res<History>,
+    current: CurrentDocument,
+    mut problems: ResMut<Problems>,
+) {
+    if !history.is_changed() {
+        return;
+    }
+    let found = validate(&current.document());
+    if problems.0 != found {
+        problems.0 = found;
+    }
+}
+
+#[derive(Component)]
+struct ProblemsPanel;
+
+/// Focuses its [`ProblemSubject`] when clicked.
+#[derive(Component)]
+struct ProblemEntry(ProblemSubject);
+
+fn add_problems_panel(trigger: Trigger<OnAdd, MainSpace>, mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(10.0),
+            bottom: Val::Px(10.0),
+            max_width: Val::Px(400.0),
+            max_height: Val::Percent(40.0),
+            padding: UiRect::all(Val::Px(5.0)),
+            flex_direction: FlexDirection::Column,
+            overflow: Overflow::clip(),
+            ..default()
+        },
+        BackgroundColor(css::DARK_GRAY.with_alpha(0.9).into()),
+        BorderRadius::all(Val::Px(5.0)),
+        FocusPolicy::Block,
+        // Above the nodes, which are spawned later
+        ZIndex(1),
+        Visibility::Hidden,
+        ProblemsPanel,
+        ChildOf(trigger.target()),
+    ));
+}
+
+fn update_problems_panel(
+    problems: Res<Problems>,
+    mut panels: Query<(Entity, &mut Visibility), With<ProblemsPanel>>,
+    mut commands: Commands,
+) {
+    if !problems.is_changed() {
+        return;
+    }
+    for (panel, mut visibility) in panels.iter_mut() {
+        *visibility = if problems.is_empty() {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        commands.entity(panel).despawn_related::<Children>();
+        commands.spawn((
+            Text(format!("Problems ({})", problems.len())),
+            TextFont::from_font_size(14.0),
+            ChildOf(panel),
+        ));
+        for problem in problems.iter() {
+            let mut entry = commands.spawn((
+                Node {
+                    margin: UiRect::top(Val::Px(3.0)),
+                    padding: UiRect::horizontal(Val::Px(3.0)),
+                    ..default()
+                },
+                BorderRadius::all(Val::Px(3.0)),
+                ChildOf(panel),
+                children![(
+                    Text(problem.message.clone()),
+                    TextFont::from_font_size(12.0),
+                    TextColor(css::ORANGE.into()),
+                )],
+            ));
+            if problem.subject != ProblemSubject::Document {
+                entry
+                    .insert((
+                        BackgroundColor(css::GRAY.with_alpha(0.3).into()),
+                        Button,
+                        ProblemEntry(problem.subject.clone()),
+                    ))
+                    .observe(focus_problem);
+            }
+        }
+    }
+}
+
+fn focus_problem(
+    trigger: Trigger<Pointer<Click>>,
+    entries: Query<&ProblemEntry>,
+    mut commands: Commands,
+) -> Result {
+    match &entries.get(trigger.target())?.0 {
+        ProblemSubject::Document => {}
+        ProblemSubject::State(id) => commands.trigger(FocusState(id.clone())),
+        ProblemSubject::Transition { from, to } => commands.trigger(FocusTransition {
+            from: from.clone(),
+            to: to.clone(),
+        }),
+    }
+    Ok(())
+}
+
+/// Shows the node's parent on the canvas, centers on the node and selects only it.
+#[derive(Event, Debug)]
+pub struct FocusState(pub StateNodeId);
+
+/// Shows the parent of the transition's source on the canvas, centers on the transition and
+/// starts editing its guard.
+#[derive(Event, Debug)]
+pub struct FocusTransition {
+    pub from: StateNodeId,
+    pub to: StateNodeId,
+}
+
+/// The entity and center, in canvas coordinates, of the node for `id`, moved into its lane if
+/// it's in a region of the parallel state the canvas shows.
+fn node_center(
+    nodes: &Query<(Entity, &State, &CanvasPosition, &ComputedNode)>,
+    canvas: &Canvas,
+    lanes: &Lanes,
+    id: &StateNodeId,
+) -> Result<(Entity, Vec2)> {
+    let (entity, state, position, computed) = nodes
+        .iter()
+        .find(|(_, state, ..)| state.id == *id)
+        .ok_or("State not found")?;
+    let size = computed.size() * computed.inverse_scale_factor() / canvas.zoom;
+    let offset = state
+        .parent
+        .as_ref()
+        .and_then(|parent| lanes.region(parent))
+        .map(LaneLayout::offset)
+        .unwrap_or_default();
+    Ok((entity, position.0 + offset + size / 2.0))
+}
+
+/// Shows the parent of `state` on the canvas, unless it's already shown as a lane.
+fn show_parent(state: &State, lanes: &Lanes, commands: &mut Commands) {
+    let in_lane = state
+        .parent
+        .as_ref()
+        .is_some_and(|parent| lanes.region(parent).is_some());
+    if !in_lane {
+        commands.trigger(EnterScope(state.parent.clone()));
+    }
+}
+
+fn center_on(canvas: &mut Canvas, target: Vec2) {
+    canvas.pan = canvas.viewport.size() / 2.0 - target * canvas.zoom;
+}
+
+fn focus_state(
+    trigger: Trigger<FocusState>,
+    nodes: Query<(Entity, &State, &CanvasPosition, &ComputedNode)>,
+    selected: Query<Entity, With<Selected>>,
+    lanes: Res<Lanes>,
+    mut canvas: ResMut<Canvas>,
+    mut commands: Commands,
+) -> Result {
+    let (node, center) = node_center(&nodes, &canvas, &lanes, &trigger.0)?;
+    let (_, state, ..) = nodes.get(node)?;
+    show_parent(state, &lanes, &mut commands);
+    for other in selected.iter().filter(|other| *other != node) {
+        commands.entity(other).remove::<Selected>();
+    }
+    commands.entity(node).insert(Selected);
+    center_on(&mut canvas, center);
+    Ok(())
+}
+
+fn focus_transition(
+    trigger: Trigger<FocusTransition>,
+    nodes: Query<(Entity, &State, &CanvasPosition, &ComputedNode)>,
+    transitions: Query<(Entity, &Transition)>,
+    mut labels: Query<(Entity, &GuardTextInput, &mut TextInputActive)>,
+    lanes: Res<Lanes>,
+    mut canvas: ResMut<Canvas>,
+    mut commands: Commands,
+) -> Result {
+    let (from, start) = node_center(&nodes, &canvas, &lanes, &trigger.from)?;
+    let (_, end) = node_center(&nodes, &canvas, &lanes, &trigger.to)?;
+    let (_, state, ..) = nodes.get(from)?;
+    show_parent(state, &lanes, &mut commands);
+    let (transition, _) = transitions
+        .iter()
+        .find(|(_, transition)| transition.from == trigger.from && transition.to == trigger.to)
+        .ok_or("Transition not found")?;
+    for (label, _, mut active) in labels
+        .iter_mut()
+        .filter(|(_, label, _)| label.0 == transition)
+    {
+        *active = TextInputActive::Active;
+        commands.entity(label).trigger(TextInputFocused);
+    }
+    center_on(&mut canvas, (start + end) / 2.0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::fixtures::{document, state, transition};
+
+    fn unreachable(document: &Document) -> Vec<String> {
+        validate(document)
+            .into_iter()
+            .filter(|problem| problem.message.contains("can't be reached"))
+            .map(|problem| problem.message)
+            .collect()
+    }
+
+    #[test]
+    fn nested_state_without_incoming_transition_is_unreachable() {
+        let start = state("Start", None, true);
+        let parent = state("Parent", None, false);
+        let first = state("First", Some(&parent), true);
+        let lonely = state("Lonely", Some(&parent), false);
+        let transitions = vec![transition(&start, &parent), transition(&first, &start)];
+        let document = document(vec![], &[&start, &parent, &first, &lonely], transitions);
+        assert_eq!(
+            unreachable(&document),
+            vec!["Lonely can't be reached from Start".to_string()]
+        );
+    }
+
+    #[test]
+    fn transition_into_a_child_skips_the_initial_one() {
+        let start = state("Start", None, true);
+        let parent = state("Parent", None, false);
+        let first = state("First", Some(&parent), true);
+        let second = state("Second", Some(&parent), false);
+        let transitions = vec![transition(&start, &second)];
+        let document = document(vec![], &[&start, &parent, &first, &second], transitions);
+        assert_eq!(
+            unreachable(&document),
+            vec!["First can't be reached from Start".to_string()]
+        );
+    }
+
+    #[test]
+    fn regions_of_a_parallel_state_are_entered_together() {
+        let start = state("Start", None, true);
+        let mut both = state("Both", None, false);
+        both.parallel = true;
+        let left = state("Left", Some(&both), false);
+        let right = state("Right", Some(&both), false);
+        let left_inner = state("Left inner", Some(&left), true);
+        let right_inner = state("Right inner", Some(&right), true);
+        let transitions = vec![transition(&start, &left_inner)];
+        let document = document(
+            vec![],
+            &[&start, &both, &left, &right, &left_inner, &right_inner],
+            transitions,
+        );
+        assert!(unreachable(&document).is_empty());
+    }
+}